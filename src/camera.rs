@@ -0,0 +1,38 @@
+use crate::consts::*;
+
+/// Part of the network shown in the window: `(x, y)` is the network point at the
+/// top-left corner of the window, `zoom` the window pixels per network pixel.
+pub struct Camera {
+    pub x: f32,
+    pub y: f32,
+    pub zoom: f32,
+}
+
+impl Camera {
+    /// Zoomed out just enough to show a network of the given size.
+    pub fn fit(width: u32, height: u32) -> Self {
+        let zoom = (SIMULATION_WINDOW_WIDTH as f32 / width as f32)
+            .min(SIMULATION_WINDOW_HEIGHT as f32 / height as f32)
+            .clamp(CAMERA_MIN_ZOOM, CAMERA_MAX_ZOOM);
+        Camera { x: 0.0, y: 0.0, zoom }
+    }
+
+    /// Network point under a window pixel.
+    pub fn to_network(&self, x: i32, y: i32) -> (i32, i32) {
+        ((self.x + x as f32 / self.zoom).floor() as i32, (self.y + y as f32 / self.zoom).floor() as i32)
+    }
+
+    /// Move the view by a drag of (dx, dy) window pixels.
+    pub fn pan(&mut self, dx: i32, dy: i32) {
+        self.x -= dx as f32 / self.zoom;
+        self.y -= dy as f32 / self.zoom;
+    }
+
+    /// Zoom in (`steps` > 0) or out, keeping the point under the cursor in place.
+    pub fn zoom_at(&mut self, x: i32, y: i32, steps: i32) {
+        let (anchor_x, anchor_y) = (self.x + x as f32 / self.zoom, self.y + y as f32 / self.zoom);
+        self.zoom = (self.zoom * CAMERA_ZOOM_STEP.powi(steps)).clamp(CAMERA_MIN_ZOOM, CAMERA_MAX_ZOOM);
+        self.x = anchor_x - x as f32 / self.zoom;
+        self.y = anchor_y - y as f32 / self.zoom;
+    }
+}
@@ -1,48 +1,160 @@
-use crate::intersection::{Direction, Route};
+use crate::crossing_manager::{Approach, SlotOffer};
+use crate::intersection::{Direction, Lane, Route};
+use crate::map::Layout;
+use crate::speed_profile::{SpeedProfile, earliest_arrival, plan_arrival};
+use crate::v2i::{Answer, Client, ClientState, Message};
 use chrono::{DateTime, Local};
+use rand::prelude::*;
+use rand::rng;
 use sdl2::pixels::Color;
+use serde::{Deserialize, Serialize};
 use sdl2::rect::Rect;
-use sdl2::render::{Canvas, Texture};
-use sdl2::video::Window;
+use sdl2::render::{Canvas, RenderTarget, Texture};
+use std::collections::VecDeque;
 use std::time::{Duration, SystemTime};
 
 use crate::consts::*;
 use crate::utils::*;
 
+/// Who drives a car. Autonomous cars book their way through the crossing manager;
+/// human drivers never talk to it and give way at the entry line instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DriverType {
+    Autonomous,
+    Human,
+}
+
+/// What a car is on the road for. Emergency vehicles are booked through ahead of
+/// every car that can still give way; buses and freight book ahead of regular
+/// cars that have not waited much longer than they have.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum VehicleClass {
+    Regular,
+    Bus,
+    Freight,
+    Emergency,
+}
+
+impl VehicleClass {
+    pub const ALL: [VehicleClass; 4] = [VehicleClass::Regular, VehicleClass::Bus, VehicleClass::Freight, VehicleClass::Emergency];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            VehicleClass::Regular => "car",
+            VehicleClass::Bus => "bus",
+            VehicleClass::Freight => "freight truck",
+            VehicleClass::Emergency => "emergency vehicle",
+        }
+    }
+
+    /// Claim on a slot after waiting `waited` to enter: the class weight, growing
+    /// by itself every `PRIORITY_AGING_SECS`. Whoever has waited past
+    /// `STARVATION_WAIT_SECS` outranks every class but emergency vehicles.
+    pub fn priority(self, waited: Duration) -> f64 {
+        let waited = waited.as_secs_f64();
+        let weight = match self {
+            VehicleClass::Emergency => return f64::INFINITY,
+            _ if waited >= STARVATION_WAIT_SECS => return f64::MAX,
+            VehicleClass::Regular => 1.0,
+            VehicleClass::Bus => BUS_PRIORITY_WEIGHT,
+            VehicleClass::Freight => FREIGHT_PRIORITY_WEIGHT,
+        };
+        weight * (1.0 + waited / PRIORITY_AGING_SECS)
+    }
+
+    /// Whether a car that has waited `waited` to enter may push others aside to
+    /// book: any class but regular cars, and anyone past `STARVATION_WAIT_SECS`.
+    pub fn may_preempt(self, waited: Duration) -> bool {
+        self != VehicleClass::Regular || waited.as_secs_f64() >= STARVATION_WAIT_SECS
+    }
+}
+
+/// Car directly ahead in the same lane, as seen by its follower.
+#[derive(Clone, Copy)]
+pub struct Leader {
+    pub gap: f64,
+    pub speed: f64,
+}
+
+/// Where and how a car joins its lane, and the slot it starts out with.
+pub struct Spawn {
+    pub id: String,
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    pub speed: f64,
+    pub route: Route,
+    pub direction: Direction,
+    pub lane: Lane,
+    pub slot: SlotOffer,
+    pub now: SystemTime,
+}
+
 pub struct Car<'b> {
     pub id: String,
     pub x: i32,
     pub y: i32,
     pub width: u32,
     pub height: u32,
-    pub speed: i32,
+    pub speed: f64,
     pub texture: &'b Texture<'b>,
+    /// Geometry of the intersection the car is crossing.
+    pub layout: &'b Layout,
     pub route: Route,
+    pub origin: Direction,
     pub direction: Direction,
+    /// Exit headings at the intersections after this one.
+    pub onward: Vec<Direction>,
+    pub lane: Lane,
+    pub target_lane: Lane,
     pub turned: bool,
     pub exited: bool,
     pub collided: bool,
     pub brake: bool,
 
+    pub time_requested: SystemTime,
     pub time_enter: SystemTime,
     pub time_exit: Option<SystemTime>,
     pub entry_time: SystemTime,
+    pub crossing_speed: f64,
     pub in_intersection: bool,
     pub dist: i32,
     pub actual_entry_time: Option<SystemTime>,
+    pub profile: SpeedProfile,
+
+    pub stopped_time: f64,
+    pub stops: usize,
+    pub braking_time: f64,
+
+    pub driver: DriverType,
+    pub class: VehicleClass,
+    /// Entry time the car was booked for before a vehicle with priority pushed it aside.
+    pub displaced_from: Option<SystemTime>,
+    /// Autonomous car whose slot went and that found no other in reach yet.
+    pub held: bool,
+    /// Where the car stands in its exchange with the crossing manager.
+    pub v2i: Client,
+    /// Human driver who has seen a clear path and is going.
+    pub cleared: bool,
+    /// Since when a human driver waiting to go has seen the path clear.
+    pub clear_since: Option<SystemTime>,
+    /// Distance driven since the entry line.
+    pub travelled_inside: f64,
+    /// Share of the speed limit a human driver is aiming for right now.
+    speed_factor: f64,
+    /// Speeds of the car ahead over the last reaction time, oldest first.
+    observed: VecDeque<(SystemTime, Option<f64>)>,
+    moving: bool,
+    travel_remainder: f64,
+    /// Distance driven round the ring of a roundabout so far.
+    ring_travelled: Option<f64>,
 }
 
 impl<'b> Car<'b> {
-    pub fn new(
-        id: String, x: i32, y: i32, width: u32, height: u32,
-        speed: i32, texture: &'b Texture<'b>,
-        route: Route, entry_time: SystemTime, direction: Direction,
-    ) -> Self {
-        let dist = match route {
-            Route::Right => ROUTE_RIGHT_DISTANCE,
-            Route::Straight => ROUTE_STRAIGHT_DISTANCE,
-            Route::Left => ROUTE_LEFT_DISTANCE,
-        };
+    pub fn new(spawn: Spawn, texture: &'b Texture<'b>, layout: &'b Layout) -> Self {
+        let Spawn { id, x, y, width, height, speed, route, direction, lane, slot, now } = spawn;
+        let dist = layout.route_length(direction, route);
 
         Car {
             id,
@@ -52,68 +164,320 @@ impl<'b> Car<'b> {
             height,
             speed,
             texture,
+            layout,
             route,
+            origin: direction,
             direction,
+            onward: Vec::new(),
+            lane,
+            target_lane: layout.target_lane(route, lane),
             turned: false,
             exited: false,
             collided: false,
-            time_enter: SystemTime::now(),
+            time_requested: now,
+            time_enter: now,
             time_exit: None,
-            entry_time,
+            entry_time: slot.entry_time,
+            crossing_speed: slot.crossing_speed,
             in_intersection: false,
             dist,
             actual_entry_time: None,
             brake: false,
+            profile: slot.profile,
+            stopped_time: 0.0,
+            stops: 0,
+            braking_time: 0.0,
+            driver: DriverType::Autonomous,
+            class: VehicleClass::Regular,
+            displaced_from: None,
+            held: false,
+            v2i: Client::new(),
+            cleared: false,
+            clear_since: None,
+            travelled_inside: 0.0,
+            speed_factor: 1.0,
+            observed: VecDeque::new(),
+            moving: false,
+            travel_remainder: 0.0,
+            ring_travelled: None,
         }
     }
 
     pub fn bounding_box(&self) -> Rect {
-        let (w, h) = match self.direction {
-            Direction::North | Direction::South => (self.width, self.height),
-            Direction::East | Direction::West => (self.height, self.width),
-        };
+        self.body().bb
+    }
 
-        Rect::new(self.x, self.y, w, h)
+    pub fn body(&self) -> Body {
+        Body::new(self.x, self.y, self.direction, self.width, self.height)
     }
 
-    pub fn intersects(&self, other: &Car) -> bool { self.bounding_box().has_intersection(other.bounding_box()) }
+    /// Body the car would have after moving sideways into `lane`.
+    pub fn body_in_lane(&self, lane: Lane) -> Body {
+        self.body().shifted_across(self.layout.car_body(self.direction, lane).center())
+    }
+
+    /// Whether the car covers part of `rect`.
+    pub fn overlaps(&self, rect: Rect) -> bool {
+        self.body().overlaps_rect(rect)
+    }
+
+    /// Adjacent lane the car still has to move into, while lane changes are allowed.
+    pub fn next_lane(&self) -> Option<Lane> {
+        if self.lane == self.target_lane || self.distance_to_entry() > LANE_CHANGE_END_PX {
+            return None;
+        }
+        Some(self.lane.towards(self.target_lane))
+    }
+
+    pub fn move_to_lane(&mut self, lane: Lane) {
+        let bb = self.body_in_lane(lane).bb;
+        self.x = bb.x();
+        self.y = bb.y();
+        self.lane = lane;
+    }
+
+    /// A car in the wrong lane treats the end of the lane change area as a stopped car.
+    pub fn lane_change_stop(&self) -> Option<Leader> {
+        if self.lane == self.target_lane {
+            return None;
+        }
+        let gap = (LANE_CHANGE_END_PX - self.distance_to_entry()).max(0) as f64;
+        Some(Leader { gap, speed: 0.0 })
+    }
+
+    pub fn intersects(&self, other: &Car) -> bool { self.body().overlaps(&other.body()) }
+
+    pub fn gap_to(&self, other: &Car) -> Option<f64> {
+        gap_by_direction(&self.body(), &other.body(), self.direction)
+            .map(|gap| gap as f64)
+    }
 
-    pub fn is_too_close(&self, other: &Car) -> bool {
-        let safe_distance = BRAKE_DISTANCE_PX;
+    pub fn distance_to_entry(&self) -> i32 { self.layout.distance_travelled(&self.body()) }
 
-        let self_box = self.bounding_box();
-        let other_box = other.bounding_box();
+    /// Distance (px) still to cover before the car reaches the intersection,
+    /// including travel not yet made up into a whole step.
+    pub fn remaining_to_entry(&self) -> f64 {
+        (self.layout.distance_to_entry_line(&self.body()) - self.travel_remainder).max(0.0)
+    }
+
+    fn time_left(&self, now: SystemTime) -> f64 {
+        self.entry_time
+            .duration_since(now)
+            .unwrap_or(Duration::ZERO)
+            .as_secs_f64()
+    }
+
+    /// Whether the booked slot is still reachable from the current position and speed.
+    pub fn can_make_slot(&self, now: SystemTime) -> bool {
+        self.driver == DriverType::Human
+            || self.layout.bypasses_zones(self.route)
+            || plan_arrival(self.speed, self.remaining_to_entry(), self.time_left(now), self.crossing_speed).is_some()
+    }
 
-        proximity_check_by_direction(&self_box, &other_box, self.direction, safe_distance)
+    /// Where the car is, as the crossing manager needs it to offer a slot.
+    pub fn approach(&self) -> Approach {
+        Approach {
+            dir: self.direction,
+            lane: self.target_lane,
+            route: self.route,
+            distance_to_entry: self.remaining_to_entry(),
+            speed: self.speed,
+            length: self.height as f64,
+        }
     }
 
-    pub fn distance_to_entry(&self) -> i32 { get_distance_by_direction(self.x, self.y, self.height, self.direction) }
+    pub fn rebook(&mut self, slot: SlotOffer) {
+        self.entry_time = slot.entry_time;
+        self.crossing_speed = slot.crossing_speed;
+        self.profile = slot.profile;
+        self.held = false;
+    }
 
-    fn is_at_entry_boundary(&self) -> bool { is_past_entry_by_direction(self.x, self.y, self.height, self.direction) }
+    /// Open a new request for a slot from where the car is now.
+    pub fn request_slot(&mut self, now: SystemTime) -> Message {
+        let Approach { dir, lane, route, distance_to_entry, speed, length } = self.approach();
+        let quickest = earliest_arrival(speed, distance_to_entry, self.crossing_speed).map_or(0.0, |p| p.duration());
+        Message::Request {
+            car_id: self.id.clone(),
+            seq: self.v2i.open(now),
+            at: now,
+            dir,
+            lane,
+            route,
+            distance_to_entry,
+            speed,
+            arrival: now + Duration::from_secs_f64(quickest),
+            length,
+            width: self.width as f64,
+            class: self.class,
+            requested_at: self.time_requested,
+        }
+    }
 
-    pub fn update(&mut self, delta_time: Duration) {
-        if self.exited || self.brake {
+    /// What the car has to tell the crossing manager this tick, if anything: a
+    /// request when it has no slot, lost track of one or can no longer make it,
+    /// and a go-ahead request once it nears the entry line. A car left without
+    /// an answer holds back until it gets one.
+    pub fn talk(&mut self, now: SystemTime) -> Option<Message> {
+        if self.v2i.timed_out(now) {
+            self.held = true;
+        }
+        let can_stop = self.remaining_to_entry() >= AV_HOLD_DISTANCE_PX || self.speed < STOPPED_SPEED_PX_PER_SEC;
+        let commit = match self.v2i.state {
+            ClientState::Idle => return Some(self.request_slot(now)),
+            ClientState::Requested { .. } if self.v2i.timed_out(now) => return Some(self.request_slot(now)),
+            ClientState::Requested { .. } | ClientState::Closed => return None,
+            _ if can_stop && !self.can_make_slot(now) => return Some(self.request_slot(now)),
+            ClientState::Confirmed { .. } => {
+                self.lane == self.target_lane
+                    && !self.layout.bypasses_zones(self.route)
+                    && self.remaining_to_entry() <= V2I_GO_AHEAD_DISTANCE_PX
+            }
+            ClientState::Committing { .. } => self.v2i.timed_out(now),
+            ClientState::Cleared { .. } => false,
+        };
+        if !commit {
+            return None;
+        }
+        Some(Message::Update {
+            car_id: self.id.clone(),
+            seq: self.v2i.commit(now)?,
+            at: now,
+            distance_to_entry: self.remaining_to_entry(),
+            speed: self.speed,
+        })
+    }
+
+    /// Take in an answer of the crossing manager.
+    pub fn receive(&mut self, message: &Message, now: SystemTime) {
+        let answer = self.v2i.receive(message);
+        if let Some(Answer::Slot { displaced: true, .. } | Answer::Refused { displaced: true }) = answer {
+            self.displaced_from.get_or_insert(self.entry_time);
+        }
+        match answer {
+            Some(Answer::Slot { entry_time, crossing_speed, .. }) => {
+                let time_left = entry_time.duration_since(now).unwrap_or_default().as_secs_f64();
+                let profile = plan_arrival(self.speed, self.remaining_to_entry(), time_left, crossing_speed)
+                    .unwrap_or(SpeedProfile::constant(crossing_speed));
+                if self.held {
+                    let datetime: DateTime<Local> = entry_time.into();
+                    println!("Car {} got a slot | Entry time: {} at {:.0} px/s", self.id, datetime.format("%H:%M:%S%.3f"), crossing_speed);
+                }
+                self.rebook(SlotOffer { entry_time, crossing_speed, profile });
+            }
+            Some(Answer::Refused { .. }) if !self.held => {
+                let who = if self.class == VehicleClass::Emergency { "Emergency vehicle" } else { "Car" };
+                println!("{} {} lost its slot and waits for another", who, self.id);
+                self.held = true;
+            }
+            Some(Answer::Refused { .. }) | None => {}
+        }
+    }
+
+    /// A human driver stops at the entry line until cleared to go. An autonomous
+    /// car with no slot waits short of it, as does one not told to go ahead by
+    /// the time it is that close, unless an answer may still be on its way.
+    pub fn entry_stop(&self, now: SystemTime) -> Option<Leader> {
+        if self.in_intersection {
+            return None;
+        }
+        let awaiting = matches!(self.v2i.state, ClientState::Requested { .. } | ClientState::Committing { .. })
+            && !self.v2i.timed_out(now);
+        let unconfirmed = !self.v2i.is_cleared()
+            && !awaiting
+            && !self.layout.bypasses_zones(self.route)
+            && self.remaining_to_entry() <= V2I_COMMIT_DISTANCE_PX;
+        let hold = match self.driver {
+            DriverType::Human if !self.cleared => 0.0,
+            DriverType::Autonomous if self.held || unconfirmed => AV_HOLD_DISTANCE_PX,
+            _ => return None,
+        };
+        Some(Leader { gap: (self.remaining_to_entry() - hold).max(0.0), speed: 0.0 })
+    }
+
+    /// Time gap kept to the car ahead.
+    pub fn time_headway(&self) -> f64 {
+        match self.driver {
+            DriverType::Autonomous => IDM_TIME_HEADWAY,
+            DriverType::Human => HUMAN_TIME_HEADWAY,
+        }
+    }
+
+    /// Speed the car would drive at on a free road.
+    fn desired_speed(&self) -> f64 {
+        SPEED_PX_PER_SEC * self.speed_factor
+    }
+
+    /// The car ahead as a human driver takes it in: where it is now, but going
+    /// as fast as it was a reaction time ago.
+    fn perceive(&mut self, now: SystemTime, leader: Option<Leader>) -> Option<Leader> {
+        if self.driver == DriverType::Autonomous {
+            return leader;
+        }
+        self.observed.push_back((now, leader.map(|l| l.speed)));
+        while self.observed.len() > 1 && self.observed[1].0 + HUMAN_REACTION_TIME <= now {
+            self.observed.pop_front();
+        }
+        let seen_speed = self.observed.front().and_then(|&(_, speed)| speed);
+        leader.map(|l| Leader { speed: seen_speed.unwrap_or(l.speed), ..l })
+    }
+
+    fn is_at_entry_boundary(&self) -> bool { self.layout.is_past_entry(&self.body()) }
+
+    /// Advance the car by `delta_time`, ending at simulation time `now`.
+    pub fn update(&mut self, now: SystemTime, delta_time: Duration, leader: Option<Leader>) {
+        if self.exited || delta_time.is_zero() {
             return;
         }
+        if self.driver == DriverType::Human {
+            let drift = rng().random_range(-1.0..=1.0) * HUMAN_SPEED_DRIFT * delta_time.as_secs_f64();
+            self.speed_factor = (self.speed_factor + drift).clamp(HUMAN_SPEED_FACTOR_MIN, 1.0);
+        }
 
         match self.route {
-            Route::Right => self.update_right_turn(delta_time),
-            Route::Left => self.update_left_turn(delta_time),
-            _ => self.update_straight(delta_time),
+            _ if self.layout.roundabout.is_some() => self.update_roundabout(now, delta_time, leader),
+            Route::Straight => self.update_straight(now, delta_time, leader),
+            _ => self.update_turn(now, delta_time, leader),
         }
+        self.record_motion(delta_time.as_secs_f64());
 
-        self.exited = has_exited_by_direction(self.x, self.y, self.height, self.direction);
+        self.exited = self.layout.has_exited(&self.body());
 
         if self.exited {
-            self.time_exit = Some(SystemTime::now());
+            self.time_exit = Some(now);
         }
     }
 
-    fn update_straight(&mut self, delta_time: Duration) {
-        let now = SystemTime::now();
+    /// Accumulate stopped time, stop count and braking time for delay metrics.
+    fn record_motion(&mut self, seconds: f64) {
+        let stopped = self.speed < STOPPED_SPEED_PX_PER_SEC;
+
+        if stopped {
+            self.stopped_time += seconds;
+            if self.moving {
+                self.stops += 1;
+            }
+        }
+        if self.brake {
+            self.braking_time += seconds;
+        }
+        if self.in_intersection {
+            self.travelled_inside += self.speed * seconds;
+        }
+        self.moving = !stopped;
+    }
+
+    fn update_straight(&mut self, now: SystemTime, delta_time: Duration, leader: Option<Leader>) {
         let seconds = delta_time.as_secs_f64();
+        let leader = self.perceive(now, leader);
 
-        if !self.in_intersection && self.is_at_entry_boundary() {
+        if !self.in_intersection && self.is_at_entry_boundary() && self.driver == DriverType::Human {
+            // Nothing was booked; a roundabout ring is driven at the speed it is joined at
+            self.in_intersection = true;
+            self.actual_entry_time = Some(now);
+            self.crossing_speed = self.speed.max(HUMAN_PROJECTED_MIN_SPEED);
+            println!("Human-driven car {} ENTERED", self.id);
+        } else if !self.in_intersection && self.is_at_entry_boundary() {
             self.in_intersection = true;
             self.actual_entry_time = Some(now);
 
@@ -130,68 +494,102 @@ impl<'b> Car<'b> {
             );
         }
 
-        let distance_to_entry = (ENTRY_DISTANCE_PX - self.distance_to_entry()).max(0);
-        let time_left = self
-            .entry_time
-            .duration_since(now)
-            .unwrap_or(Duration::ZERO)
-            .as_secs_f64();
+        let distance_to_entry = self.remaining_to_entry();
+        let time_left = self.time_left(now);
 
-        let target_speed = calculate_speed_from_distance_time(
-            distance_to_entry,
-            time_left,
-            SPEED_PX_PER_SEC
+        // Replan every tick so a car held up by its leader still aims for its slot
+        self.profile = if self.driver == DriverType::Human {
+            SpeedProfile::constant(self.desired_speed())
+        } else if self.layout.bypasses_zones(self.route) || self.in_intersection {
+            SpeedProfile::constant(self.crossing_speed)
+        } else {
+            let earliest = earliest_arrival(self.speed, distance_to_entry, self.crossing_speed);
+            match plan_arrival(self.speed, distance_to_entry, time_left, self.crossing_speed) {
+                Some(profile) => profile,
+                // A car waiting at rest for a slot too far off to drive to stays put
+                None if self.speed < STOPPED_SPEED_PX_PER_SEC && earliest.is_some_and(|p| p.duration() < time_left) => {
+                    SpeedProfile::constant(0.0)
+                }
+                None => earliest.unwrap_or(SpeedProfile::constant(self.crossing_speed)),
+            }
+        };
+        let target_speed = self.profile.speed_at(seconds);
+
+        // Blend the schedule with car following: take whichever asks for less. An
+        // autonomous car's schedule already keeps to the acceleration limit, so it
+        // is not eased off as it nears the speed limit, or it would run late
+        let schedule_accel = (target_speed - self.speed) / seconds;
+        let free_speed = if self.driver == DriverType::Human { self.desired_speed() } else { f64::INFINITY };
+        let follow_accel = idm_acceleration(
+            self.speed,
+            free_speed,
+            self.time_headway(),
+            leader.map(|l| (l.gap, self.speed - l.speed)),
         );
 
-        if self.speed < target_speed {
-            self.speed = (self.speed + MAX_ACCELERATION).min(target_speed).min(SPEED_PX_PER_SEC as i32);
-        } else if self.speed > target_speed {
-            self.speed = (self.speed - MAX_ACCELERATION).max(target_speed).max(0);
-        }
+        self.brake = follow_accel < -IDM_COMFORT_DECELERATION;
+        self.speed = (self.speed + schedule_accel.min(follow_accel) * seconds).clamp(0.0, SPEED_PX_PER_SEC);
 
-        if self.route == Route::Right { self.speed = SPEED_PX_PER_SEC as i32 }
-        let distance = (self.speed as f64 * seconds).round() as i32;
+        // Carry sub-pixel travel over so slow cars still creep forward
+        let step = self.direction.step_length();
+        let travel = self.speed * seconds + self.travel_remainder;
+        let steps = (travel / step).round() as i32;
+        self.travel_remainder = travel - steps as f64 * step;
 
-        apply_movement_for_direction(&mut self.x, &mut self.y, distance, self.direction);
+        apply_movement_for_direction(&mut self.x, &mut self.y, steps, self.direction);
     }
 
-    fn update_right_turn(&mut self, delta_time: Duration) {
-        let seconds = delta_time.as_secs_f64();
-        let distance = (self.speed as f64 * seconds).round() as i32;
-        let distance_forward = self.distance_to_entry();
+    /// Drive on to where the route crosses its exit lane, then swing onto it.
+    fn update_turn(&mut self, now: SystemTime, delta_time: Duration, leader: Option<Leader>) {
+        let distance = self.speed * delta_time.as_secs_f64();
+        let turn_at = if self.turned { None } else { self.layout.turn_distance(self.direction, self.lane, self.route) };
+        let short = turn_at.map(|at| (at - self.distance_to_entry() as f64).max(0.0));
+        let Some(short) = short.filter(|&short| short <= distance) else {
+            self.update_straight(now, delta_time, leader);
+            return;
+        };
 
-        if self.turned || distance_forward < RIGHT_TURN_ENTRY_DISTANCE_PX {
-            self.update_straight(delta_time);
-        } else {
-            let (new_x, new_y, new_direction) = get_position_after_turn(
-                self.x, self.y, distance, self.direction, Route::Right
-            );
-            self.x = new_x;
-            self.y = new_y;
-            self.direction = new_direction;
-            self.turned = true;
-        }
+        // Swing round right where the lanes cross, as the zone path has it
+        let body = self.layout.body_after_turn(&self.body().moved(short), self.route, (distance - short).round());
+        (self.x, self.y) = (body.bb.x(), body.bb.y());
+        self.direction = body.heading;
+        self.turned = true;
     }
 
-    fn update_left_turn(&mut self, delta_time: Duration) {
-        let seconds = delta_time.as_secs_f64();
-        let distance = (self.speed as f64 * seconds).round() as i32;
-        let distance_forward = self.distance_to_entry();
+    /// Drive up to the ring, hop onto it, go round at the crossing speed the slot
+    /// was booked for, then straight out along the exit arm.
+    fn update_roundabout(&mut self, now: SystemTime, delta_time: Duration, leader: Option<Leader>) {
+        if self.turned || !self.in_intersection {
+            self.update_straight(now, delta_time, leader);
+            return;
+        }
 
-        if self.turned || distance_forward < LEFT_TURN_ENTRY_DISTANCE_PX {
-            self.update_straight(delta_time);
-        } else {
-            let (new_x, new_y, new_direction) = get_position_after_turn(
-                self.x, self.y, distance, self.direction, Route::Left
-            );
-            self.x = new_x;
-            self.y = new_y;
-            self.direction = new_direction;
+        let layout = self.layout;
+        let ring = layout.roundabout.as_ref().unwrap();
+        let travelled = self.ring_travelled.unwrap_or(0.0) + self.crossing_speed * delta_time.as_secs_f64();
+        self.ring_travelled = Some(travelled);
+        self.speed = self.crossing_speed;
+
+        if travelled >= ring.arc_length(self.origin, self.route) {
+            let exit = destination_of(self.origin, self.route);
+            let body = layout.ring_exit_body(exit, self.route, self.width, self.height);
+            (self.x, self.y) = (body.bb.x(), body.bb.y());
+            self.direction = exit;
             self.turned = true;
+            return;
         }
+
+        // `travelled` is measured at the front bumper; the rest of the car stays
+        // at the entry point until it has all joined the ring
+        let middle = (travelled - self.height as f64 / 2.0).max(0.0);
+        let ((center_x, center_y), facing) = ring.point(self.origin, middle);
+        self.direction = facing;
+        let bb = self.bounding_box();
+        self.x = center_x.round() as i32 - bb.width() as i32 / 2;
+        self.y = center_y.round() as i32 - bb.height() as i32 / 2;
     }
 
-    pub fn draw(&self, canvas: &mut Canvas<Window>) {
+    pub fn draw<T: RenderTarget>(&self, canvas: &mut Canvas<T>) {
         let (w, h) = (self.width, self.height);
 
         // Offset position to account for center-based rotation
@@ -204,8 +602,25 @@ impl<'b> Car<'b> {
             .unwrap();
 
         let state_color = get_color_by_state(self.collided, self.brake, self.in_intersection);
+        let body = self.body();
         canvas.set_draw_color(state_color);
-        canvas.draw_rect(self.bounding_box()).unwrap();
+        canvas.draw_lines(&body.outline()[..]).unwrap();
+        if self.driver == DriverType::Human {
+            let inner = Body::centred(body.center(), self.direction, self.width - 6, self.height - 6);
+            canvas.set_draw_color(HUMAN_DRIVER_COLOR);
+            canvas.draw_lines(&inner.outline()[..]).unwrap();
+        }
+        let marker = match self.class {
+            VehicleClass::Regular => None,
+            VehicleClass::Bus => Some(BUS_MARKER_COLOR),
+            VehicleClass::Freight => Some(FREIGHT_MARKER_COLOR),
+            VehicleClass::Emergency => Some(EMERGENCY_LIGHT_COLOR),
+        };
+        if let Some(color) = marker {
+            let bb = self.bounding_box();
+            canvas.set_draw_color(color);
+            canvas.fill_rect(Rect::new(bb.center().x() - 4, bb.center().y() - 4, 8, 8)).unwrap();
+        }
 
         canvas.set_draw_color(Color::BLUE);
         let origin = create_origin_rect(self.x, self.y, 4);
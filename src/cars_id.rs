@@ -1,42 +1,52 @@
 use crate::intersection::{Direction, Route};
 
+/// Hands out car numbers without end, so a run lasting hours never reuses one.
+/// Numbers are padded to four digits and simply grow wider past 9999.
 pub struct CarIdGenerator {
     current: usize,
-    max: usize,
+    /// Number of the intersection handing out the ids, in a network of several,
+    /// so cars from different ones never share an id.
+    pub node: Option<usize>,
 }
 
 impl CarIdGenerator {
     pub fn new() -> Self {
         CarIdGenerator {
             current: 0,
-            max: 9999,
+            node: None,
         }
     }
 
     pub fn get_next(&mut self, direction: Direction, route: Route) -> String {
-        let prefix = match (direction, route) {
-            (Direction::South, Route::Right)    => "SRT",
-            (Direction::South, Route::Left)     => "SLT",
-            (Direction::South, Route::Straight) => "SST",
-            (Direction::North, Route::Right)    => "NRT",
-            (Direction::North, Route::Left)     => "NLT",
-            (Direction::North, Route::Straight) => "NST",
-            (Direction::East,  Route::Right)    => "ERT",
-            (Direction::East,  Route::Left)     => "ELT",
-            (Direction::East,  Route::Straight) => "EST",
-            (Direction::West,  Route::Right)    => "WRT",
-            (Direction::West,  Route::Left)     => "WLT",
-            (Direction::West,  Route::Straight) => "WST",
+        let heading = match direction {
+            Direction::North     => "N",
+            Direction::South     => "S",
+            Direction::East      => "E",
+            Direction::West      => "W",
+            Direction::NorthEast => "NE",
+            Direction::SouthEast => "SE",
+            Direction::SouthWest => "SW",
+            Direction::NorthWest => "NW",
         };
-        format!("{}-{:04}", prefix, self.next().unwrap())
+        let turn = match route {
+            Route::Right      => "RT",
+            Route::Left       => "LT",
+            Route::Straight   => "ST",
+            Route::BearRight  => "BR",
+            Route::BearLeft   => "BL",
+            Route::SharpRight => "SR",
+            Route::SharpLeft  => "SL",
+        };
+        let prefix = format!("{}{}", heading, turn);
+        let number = self.next();
+        match self.node {
+            Some(node) => format!("{}-{}.{:04}", prefix, node, number),
+            None => format!("{}-{:04}", prefix, number),
+        }
     }
 
-    pub fn next(&mut self) -> Option<usize> {
-        if self.current >= self.max {
-            None // Stop at 9999
-        } else {
-            self.current += 1;
-            Some(self.current)
-        }
+    pub fn next(&mut self) -> usize {
+        self.current += 1;
+        self.current
     }
 }
@@ -0,0 +1,71 @@
+use crate::consts::*;
+use std::time::Duration;
+
+/// Turns wall-clock frame time into fixed `BASE_DELTA_TIME` simulation ticks,
+/// honouring pause, single-step and the selected time scale.
+pub struct SimClock {
+    pub paused: bool,
+    scale_index: usize,
+    steps_requested: usize,
+    accumulated: Duration,
+}
+
+impl SimClock {
+    pub fn new() -> Self {
+        SimClock {
+            paused: false,
+            scale_index: TIME_SCALES.iter().position(|&s| s == 1.0).unwrap_or(0),
+            steps_requested: 0,
+            accumulated: Duration::ZERO,
+        }
+    }
+
+    pub fn scale(&self) -> f64 {
+        TIME_SCALES[self.scale_index]
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+        self.accumulated = Duration::ZERO;
+    }
+
+    /// Pause (if running) and advance by exactly one tick.
+    pub fn step(&mut self) {
+        self.paused = true;
+        self.steps_requested += 1;
+    }
+
+    pub fn faster(&mut self) {
+        self.scale_index = (self.scale_index + 1).min(TIME_SCALES.len() - 1);
+    }
+
+    pub fn slower(&mut self) {
+        self.scale_index = self.scale_index.saturating_sub(1);
+    }
+
+    /// Number of ticks to simulate for a frame that took `frame_time` of wall time.
+    /// Time left over carries into the next frame so slow scales still move.
+    pub fn ticks(&mut self, frame_time: Duration) -> usize {
+        if self.paused {
+            return std::mem::take(&mut self.steps_requested);
+        }
+
+        self.accumulated += frame_time.mul_f64(self.scale());
+        let ticks = (self.accumulated.as_nanos() / BASE_DELTA_TIME.as_nanos()) as usize;
+        if ticks > MAX_TICKS_PER_FRAME {
+            // Falling behind; drop the backlog rather than stall the window
+            self.accumulated = Duration::ZERO;
+            return MAX_TICKS_PER_FRAME;
+        }
+        self.accumulated -= BASE_DELTA_TIME * ticks as u32;
+        ticks
+    }
+
+    pub fn label(&self) -> String {
+        if self.paused {
+            "paused".to_string()
+        } else {
+            format!("x{}", self.scale())
+        }
+    }
+}
@@ -1,70 +1,131 @@
-use crate::crossing_manager::ZoneIndex;
+use crate::intersection::Route;
 use sdl2::pixels::Color;
 use std::time::Duration;
 
 pub const BASE_DELTA_TIME: Duration = Duration::from_millis(16);
+pub const TIME_SCALES: [f64; 7] = [0.25, 0.5, 1.0, 2.0, 3.0, 5.0, 10.0];
+pub const MAX_TICKS_PER_FRAME: usize = 20;
+pub const CSV_SAMPLE_INTERVAL: Duration = Duration::from_millis(250);
+pub const RENDER_FRAMES: usize = 600;
+pub const MAX_PATH_TURNS: usize = 8;
+/// Distance past the end of a route before the whole car has left the tile.
+pub const TILE_EXIT_MARGIN_PX: i32 = CAR_HEIGHT_PX as i32;
+pub const RENDER_FRAME_TICKS: usize = 2;
 
 pub const MAX_SPEED: i32 = 5;
 pub const BRAKE_DISTANCE_PX: i32 = 10;
 
+// Intelligent Driver Model parameters for in-lane car following
+pub const IDM_MAX_ACCELERATION: f64 = 600.0; // px/s^2
+pub const IDM_COMFORT_DECELERATION: f64 = 900.0; // px/s^2
+pub const IDM_TIME_HEADWAY: f64 = 0.2; // s
+pub const IDM_ACCELERATION_EXPONENT: i32 = 4;
+
+// Human drivers: they react late, keep a longer headway, let their speed wander
+// and give way at the entry line until their path is clear
+pub const HUMAN_REACTION_TIME: Duration = Duration::from_millis(800);
+pub const HUMAN_TIME_HEADWAY: f64 = 1.2; // s
+pub const HUMAN_SPEED_FACTOR_MIN: f64 = 0.8;
+pub const HUMAN_SPEED_DRIFT: f64 = 0.15; // speed factor change per s
+pub const HUMAN_CRITICAL_GAP: f64 = 2.0; // s
+pub const HUMAN_DECISION_DISTANCE_PX: f64 = 150.0;
+
+// The manager assumes an observed human driver may go no slower than this while crossing
+pub const HUMAN_PROJECTED_MIN_SPEED: f64 = 100.0;
+// An AV with no slot it can reach waits this far short of the entry line
+pub const AV_HOLD_DISTANCE_PX: f64 = 100.0;
+
+// V2I link: an unanswered message is sent again after the timeout. A car asks to
+// go ahead on its slot at the first distance and, without an answer by the
+// second, waits short of the entry line. Idle sessions are dropped after the TTL
+pub const V2I_REPLY_TIMEOUT: Duration = Duration::from_millis(250);
+pub const V2I_GO_AHEAD_DISTANCE_PX: f64 = 250.0;
+pub const V2I_COMMIT_DISTANCE_PX: f64 = 180.0;
+pub const V2I_SESSION_TTL: Duration = Duration::from_secs(30);
+
+// Slot priority: a class weight that grows with the time a car has waited to enter.
+// Past the starvation limit nobody but an emergency vehicle books ahead of it
+pub const BUS_PRIORITY_WEIGHT: f64 = 3.0;
+pub const FREIGHT_PRIORITY_WEIGHT: f64 = 2.0;
+pub const PRIORITY_AGING_SECS: f64 = 10.0;
+pub const STARVATION_WAIT_SECS: f64 = 30.0;
+
+// Arrival planner: cruise speeds tried per search, and bisection steps to refine
+pub const PROFILE_SEARCH_STEPS: u32 = 60;
+pub const PROFILE_REFINE_ITERATIONS: u32 = 20;
+pub const MAX_SLOT_ATTEMPTS: u32 = 50;
+
+// Below this speed a car counts as stopped for delay metrics
+pub const STOPPED_SPEED_PX_PER_SEC: f64 = 10.0;
+
 // pub const RIGHT_TURN_SPEED_PX: i32 = 7;
-pub const ENTRY_DISTANCE_PX: i32 = 350;
 pub const ENTRY_DISTANCE_PX_RIGHT: i32 = 300;
-pub const LEFT_TURN_ENTRY_DISTANCE_PX: i32 = 500;
-pub const RIGHT_TURN_ENTRY_DISTANCE_PX: i32 = 350;
 
-pub const ZONE_LENGTH_PX: f64 = 50.0;
 pub const SPEED_PX_PER_SEC: f64 = MAX_SPEED as f64 * 60.0;
 pub const SAFE_DISTANCE_PX: f64 = CAR_HEIGHT_PX as f64 / 2.0;
 
+// Crossing speeds the manager may offer per route, fastest first
+pub const CROSSING_SPEEDS_STRAIGHT: &[f64] = &[SPEED_PX_PER_SEC, SPEED_PX_PER_SEC * 0.75];
+pub const CROSSING_SPEEDS_LEFT: &[f64] = &[SPEED_PX_PER_SEC * 0.75, SPEED_PX_PER_SEC * 0.5];
+pub const CROSSING_SPEEDS_RIGHT: &[f64] = &[SPEED_PX_PER_SEC];
+
 pub const CAR_WIDTH_PX: u32 = 33;
 pub const CAR_HEIGHT_PX: u32 = 78;
 
-pub const SPAWN_POSITION_SOUTH_LEFT: (i32, i32, i32) = (408, -80, 5);
-pub const SPAWN_POSITION_SOUTH_STRAIGHT: (i32, i32, i32) = (358, -80, 5);
-pub const SPAWN_POSITION_SOUTH_RIGHT: (i32, i32, i32) = (308, -80, 7);
-
-pub const SPAWN_POSITION_NORTH_LEFT: (i32, i32, i32) = (458, 900, 5);
-pub const SPAWN_POSITION_NORTH_STRAIGHT: (i32, i32, i32) = (508, 900, 5);
-pub const SPAWN_POSITION_NORTH_RIGHT: (i32, i32, i32) = (558, 900, 7);
-
-pub const SPAWN_POSITION_EAST_LEFT: (i32, i32, i32) = (-80, 458, 5);
-pub const SPAWN_POSITION_EAST_STRAIGHT: (i32, i32, i32) = (-80, 508, 5);
-pub const SPAWN_POSITION_EAST_RIGHT: (i32, i32, i32) = (-80, 558, 7);
-
-pub const SPAWN_POSITION_WEST_LEFT: (i32, i32, i32) = (900, 408, 5);
-pub const SPAWN_POSITION_WEST_STRAIGHT: (i32, i32, i32) = (900, 358, 5);
-pub const SPAWN_POSITION_WEST_RIGHT: (i32, i32, i32) = (900, 308, 7);
-
-pub const ZONES_FOR_SOUTH_LEFT: &[ZoneIndex] = &[(0, 1), (1, 1), (2, 1), (2, 2), (2, 3)];
-pub const ZONES_FOR_SOUTH_STRAIGHT: &[ZoneIndex] = &[(0, 0), (1, 0), (2, 0), (3, 0)];
+// Pedestrians are squares walking at a steady pace across their crosswalk
+pub const PEDESTRIAN_SIZE_PX: u32 = 12;
+pub const PEDESTRIAN_SPEED_PX_PER_SEC: f64 = 30.0;
 
-pub const ZONES_FOR_NORTH_LEFT: &[ZoneIndex] = &[(3, 2), (2, 2), (1, 2), (1, 1), (1, 0)];
-pub const ZONES_FOR_NORTH_STRAIGHT: &[ZoneIndex] = &[(3, 3), (2, 3), (1, 3), (0, 3)];
-
-pub const ZONES_FOR_EAST_LEFT: &[ZoneIndex] = &[(2, 0), (2, 1), (2, 2), (1, 2), (0, 2)];
-pub const ZONES_FOR_EAST_STRAIGHT: &[ZoneIndex] = &[(3, 0), (3, 1), (3, 2), (3, 3)];
-
-pub const ZONES_FOR_WEST_LEFT: &[ZoneIndex] = &[(1, 3), (1, 2), (1, 1), (2, 1), (3, 1)];
-pub const ZONES_FOR_WEST_STRAIGHT: &[ZoneIndex] = &[(0, 3), (0, 2), (0, 1), (0, 0)];
+// Routes each lane may be used for, and where lane changes must be done by
+pub const LANE_ROUTES_CURB: &[Route] = &[Route::Right];
+pub const LANE_ROUTES_MIDDLE: &[Route] = &[Route::Straight];
+pub const LANE_ROUTES_MEDIAN: &[Route] = &[Route::Left, Route::Straight];
+pub const LANE_CHANGE_END_PX: i32 = 200;
 
 pub const SIMULATION_WINDOW_WIDTH: u32 = 900;
 pub const SIMULATION_WINDOW_HEIGHT: u32 = 900;
 pub const STATS_WINDOW_WIDTH: u32 = 400;
-pub const STATS_WINDOW_HEIGHT: u32 = 400;
+pub const STATS_WINDOW_HEIGHT: u32 = 800;
 pub const FONT_SIZE: u16 = 20;
 pub const UI_PADDING_X: i32 = 10;
 pub const UI_PADDING_Y: i32 = 10;
 
+pub const FONT_PATH: &str = "assets/Roboto-Regular.ttf";
+pub const CAMERA_MIN_ZOOM: f32 = 0.1;
+pub const CAMERA_MAX_ZOOM: f32 = 3.0;
+pub const CAMERA_ZOOM_STEP: f32 = 1.1;
+
+pub const HUD_FONT_SIZE: u16 = 14;
+pub const HUD_WIDTH_PX: u32 = 240;
+pub const HUD_SPARKLINE_HEIGHT_PX: u32 = 40;
+pub const HUD_SPARKLINE_BUCKETS: usize = 60;
+pub const HUD_SPARKLINE_BUCKET: Duration = Duration::from_secs(1);
+pub const HUD_FPS_WINDOW: usize = 60;
+pub const HUD_BACKGROUND_COLOR: Color = Color::RGBA(0, 0, 0, 170);
+pub const HUD_SPARKLINE_COLOR: Color = Color::RGB(90, 200, 120);
+pub const HUD_SELECTION_COLOR: Color = Color::CYAN;
+pub const HUD_INSPECTOR_WIDTH_PX: u32 = 260;
+
+pub const GANTT_WINDOW_WIDTH: u32 = 900;
+pub const GANTT_WINDOW_HEIGHT: u32 = 540;
+pub const GANTT_LABEL_WIDTH_PX: i32 = 60;
+pub const GANTT_HEADER_HEIGHT_PX: i32 = 30;
+pub const GANTT_ROW_HEIGHT_PX: i32 = 30;
+pub const GANTT_PAST: Duration = Duration::from_secs(2);
+pub const GANTT_FUTURE: Duration = Duration::from_secs(6);
+pub const GANTT_VIOLATION_GAP: Duration = Duration::from_millis(100);
+pub const GANTT_BAR_COLOR: Color = Color::RGB(70, 110, 200);
+pub const GANTT_ACTIVE_COLOR: Color = Color::RGB(160, 32, 240);
+pub const GANTT_CONFLICT_COLOR: Color = Color::RED;
+pub const GANTT_GRID_COLOR: Color = Color::RGB(60, 60, 60);
+
 pub const BACKGROUND_COLOR: Color = Color::RGB(30, 30, 30);
+pub const ROAD_COLOR: Color = Color::RGB(70, 70, 70);
 pub const INTERSECTION_COLOR: Color = Color::RGB(128, 128, 128);
-
-pub const MAX_ACCELERATION: i32 = 30;
-
-pub const ROUTE_RIGHT_DISTANCE: i32 = 650;
-pub const ROUTE_STRAIGHT_DISTANCE: i32 = 900;
-pub const ROUTE_LEFT_DISTANCE: i32 = 950;
-
-pub const INTERSECTION_RECT_SIZE: i32 = 300;
-pub const INTERSECTION_START_X: i32 = 350;
-pub const INTERSECTION_START_Y: i32 = 350;
\ No newline at end of file
+pub const HUMAN_DRIVER_COLOR: Color = Color::WHITE;
+pub const EMERGENCY_LIGHT_COLOR: Color = Color::RGB(230, 30, 30);
+pub const BUS_MARKER_COLOR: Color = Color::RGB(0, 170, 90);
+pub const FREIGHT_MARKER_COLOR: Color = Color::RGB(150, 90, 40);
+pub const ROUNDABOUT_ISLAND_COLOR: Color = Color::RGB(60, 110, 60);
+pub const PEDESTRIAN_COLOR: Color = Color::RGB(255, 200, 0);
+pub const PEDESTRIAN_CONFLICT_COLOR: Color = Color::RED;
\ No newline at end of file
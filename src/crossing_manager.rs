@@ -1,63 +1,103 @@
-use crate::intersection::{Direction, Route};
-use crate::utils::route_to_zone_path;
+use crate::intersection::{Direction, Lane, Route};
+use crate::map::Layout;
+use crate::utils::{crossing_speeds, zone_window};
 use crate::utils::generate_zone_reservations;
+use crate::speed_profile::{SpeedProfile, earliest_arrival, plan_arrival};
+use crate::v2i::ZoneSlot;
 use std::collections::HashMap;
 use std::time::{SystemTime, Duration};
 use sdl2::pixels::Color;
-use sdl2::rect::Rect;
-use sdl2::render::Canvas;
-use sdl2::video::Window;
+use sdl2::render::{Canvas, RenderTarget};
 
 use crate::consts::*;
 
 pub type ZoneIndex = (usize, usize);
 
+/// Zone on a road user's path, with how far its front has come from the start
+/// of the path when it reaches the zone and when its front is past it; the
+/// whole body has cleared the zone once the rear is past as well.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PathZone {
+    pub zone: ZoneIndex,
+    pub reach: f64,
+    pub leave: f64,
+}
+
+impl PathZone {
+    /// Path through `zones` one after another, a zone length apiece.
+    pub fn in_turn(zones: &[ZoneIndex], zone_length: f64) -> Vec<PathZone> {
+        zones
+            .iter()
+            .enumerate()
+            .map(|(i, &zone)| PathZone { zone, reach: zone_length * i as f64, leave: zone_length * (i + 1) as f64 })
+            .collect()
+    }
+}
+
 #[derive(Clone)]
 pub struct ZoneReservation {
-    pub _car_id: String,
+    pub car_id: String,
     pub time_in: SystemTime,
     pub time_out: SystemTime,
 }
 
-pub struct CrossingManager {
-    pub grid: HashMap<ZoneIndex, Vec<ZoneReservation>>,
+/// Slot offered to a car: when to enter and how fast to cross.
+#[derive(Clone, Copy)]
+pub struct SlotOffer {
+    pub entry_time: SystemTime,
+    pub crossing_speed: f64,
+    pub profile: SpeedProfile,
 }
 
-impl CrossingManager {
-    pub fn new() -> Self {
-        let mut grid = HashMap::new();
+/// Where a car is when it asks for a slot: its approach, how far it still has to
+/// go to the intersection, how fast it is going and how long it is.
+#[derive(Clone, Copy)]
+pub struct Approach {
+    pub dir: Direction,
+    pub lane: Lane,
+    pub route: Route,
+    pub distance_to_entry: f64,
+    pub speed: f64,
+    pub length: f64,
+}
 
-        for y in 0..4 {
-            for x in 0..4 {
-                grid.insert((x, y), Vec::new());
-            }
-        }
-        CrossingManager { grid }
-    }
+pub struct CrossingManager<'a> {
+    pub layout: &'a Layout,
+    pub grid: HashMap<ZoneIndex, Vec<ZoneReservation>>,
+    /// Simulation time of the last update; offers are computed relative to it.
+    pub now: SystemTime,
+}
 
-    pub fn latest_available_time(&self, dir: Direction, route: Route, distance_to_entry: f64) -> SystemTime {
-        let path = route_to_zone_path(dir, route);
-        let now = SystemTime::now();
+impl<'a> CrossingManager<'a> {
+    pub fn new(layout: &'a Layout, now: SystemTime) -> Self {
+        let grid = layout.zones().map(|zone| (zone, Vec::new())).collect();
+        CrossingManager { layout, grid, now }
+    }
 
-        let zone_time = Duration::from_secs_f64(ZONE_LENGTH_PX / SPEED_PX_PER_SEC);
-        let car_occupy_time = Duration::from_secs_f64(CAR_HEIGHT_PX as f64 / SPEED_PX_PER_SEC);
-        let safe_time_gap = Duration::from_secs_f64(SAFE_DISTANCE_PX / SPEED_PX_PER_SEC);
-        let travel_time = Duration::from_secs_f64(distance_to_entry / SPEED_PX_PER_SEC);
+    pub fn latest_available_time(
+        &self, dir: Direction, lane: Lane, route: Route, earliest: SystemTime, crossing_speed: f64, length: f64,
+    ) -> SystemTime {
+        let path = self.layout.timed_zone_path(dir, lane, route);
+        self.first_free_time(&path, earliest, crossing_speed, length)
+    }
 
-        let mut base_time = now + travel_time;
+    /// Earliest time from `earliest` at which a road user `body_length` long can
+    /// cross `path` at `speed` without overlapping any reserved window.
+    fn first_free_time(&self, path: &[PathZone], earliest: SystemTime, speed: f64, body_length: f64) -> SystemTime {
+        let mut base_time = earliest;
 
         // Loop until we find a time with no conflicts + safety gap
         let base_time = 'try_time: loop {
-            for (i, zone) in path.iter().enumerate() {
-                let zone_entry_time = base_time + zone_time * i as u32;
-                let zone_exit_time = zone_entry_time  + zone_time + car_occupy_time + safe_time_gap;
+            for zone in path {
+                let (zone_entry_time, zone_exit_time) = zone_window(base_time, zone, speed, body_length);
 
-                if let Some(res_list) = self.grid.get(zone) {
+                if let Some(res_list) = self.grid.get(&zone.zone) {
                     for res in res_list {
                         let overlaps = res.time_in < zone_exit_time && res.time_out > zone_entry_time;
                         if overlaps {
                             // Conflict — delay base_time and restart
-                            base_time = base_time.max(res.time_out - zone_time * i as u32);
+                            let reach_time = Duration::from_secs_f64(zone.reach / speed);
+                            base_time = base_time.max(res.time_out - reach_time);
                             continue 'try_time;
                         }
                     }
@@ -68,72 +108,225 @@ impl CrossingManager {
         base_time
     }
 
-    pub fn reserve_path(&mut self, car_id: &str, dir: Direction, route: Route, distance_to_entry: f64) -> SystemTime {
-        let entry_time = self.latest_available_time(dir, route, distance_to_entry);
-        let path = route_to_zone_path(dir, route);
-        let reservations = generate_zone_reservations(car_id, &path, entry_time);
+    /// First free slot the car can actually reach when crossing at `crossing_speed`,
+    /// given it is at `approach` at time `from`.
+    /// Slots the arrival planner rejects are skipped in favour of later ones.
+    fn find_slot(&self, approach: Approach, from: SystemTime, crossing_speed: f64) -> Option<SlotOffer> {
+        let Approach { dir, lane, route, distance_to_entry, speed, length } = approach;
+        let quickest = earliest_arrival(speed, distance_to_entry, crossing_speed)?;
+        let mut earliest = from + Duration::from_secs_f64(quickest.duration());
+
+        for _ in 0..MAX_SLOT_ATTEMPTS {
+            let entry_time = self.latest_available_time(dir, lane, route, earliest, crossing_speed, length);
+            let time_left = entry_time.duration_since(from).unwrap_or(Duration::ZERO).as_secs_f64();
+
+            match plan_arrival(speed, distance_to_entry, time_left, crossing_speed) {
+                Some(profile) => return Some(SlotOffer { entry_time, crossing_speed, profile }),
+                None => earliest = entry_time + BASE_DELTA_TIME,
+            }
+        }
+
+        None
+    }
+
+    /// Offer the slot-and-speed pair that clears the intersection soonest and book
+    /// it, for a car at `approach` at time `from`, which may still be upstream.
+    /// The offer's profile starts at `from`.
+    pub fn reserve_path_from(&mut self, car_id: &str, approach: Approach, from: SystemTime) -> Option<SlotOffer> {
+        let path = self.layout.timed_zone_path(approach.dir, approach.lane, approach.route);
+
+        let offer = crossing_speeds(approach.route)
+            .iter()
+            .filter_map(|&crossing_speed| self.find_slot(approach, from, crossing_speed))
+            .min_by_key(|offer| {
+                path.iter()
+                    .map(|zone| zone_window(offer.entry_time, zone, offer.crossing_speed, approach.length).1)
+                    .max()
+                    .unwrap_or(offer.entry_time)
+            })?;
+
+        self.book(car_id, &path, offer.entry_time, offer.crossing_speed, approach.length);
+        Some(offer)
+    }
+
+    /// Book the soonest slot a car with priority at `approach` at time `from` can
+    /// reach, as if every window not yet started and held by someone `movable`
+    /// were free, then drop all bookings of those whose windows clash with it.
+    /// Returns the offer and the ids pushed aside, who have to book again.
+    pub fn preempt_path(
+        &mut self, car_id: &str, approach: Approach, from: SystemTime, movable: impl Fn(&str) -> bool,
+    ) -> Option<(SlotOffer, Vec<String>)> {
+        let now = self.now;
+        let mut set_aside = Vec::new();
+        for (&zone, res_list) in self.grid.iter_mut() {
+            let (kept, moved): (Vec<ZoneReservation>, Vec<ZoneReservation>) =
+                res_list.drain(..).partition(|res| res.time_in <= now || !movable(&res.car_id));
+            *res_list = kept;
+            set_aside.extend(moved.into_iter().map(|res| (zone, res)));
+        }
+
+        let offer = self.reserve_path_from(car_id, approach, from);
+        let mut displaced: Vec<String> = Vec::new();
+        for (zone, res) in &set_aside {
+            let clashes = self.grid[zone]
+                .iter()
+                .any(|own| own.car_id == car_id && own.time_in < res.time_out && res.time_in < own.time_out);
+            if clashes && !displaced.contains(&res.car_id) {
+                displaced.push(res.car_id.clone());
+            }
+        }
+        for (zone, res) in set_aside {
+            if !displaced.contains(&res.car_id) {
+                self.grid.get_mut(&zone).unwrap().push(res);
+            }
+        }
 
-        for (zone, reservation) in reservations {
+        offer.map(|offer| (offer, displaced))
+    }
+
+    /// Book the first walk across `cells` at `speed` a pedestrian waiting at the
+    /// kerb since `from` can make, first come first served with the cars.
+    /// Returns when the pedestrian should step off.
+    pub fn reserve_walk(&mut self, pedestrian_id: &str, cells: &[ZoneIndex], from: SystemTime, speed: f64) -> SystemTime {
+        let body_length = PEDESTRIAN_SIZE_PX as f64;
+        let path = PathZone::in_turn(cells, self.layout.zone_length());
+        let start = self.first_free_time(&path, from, speed, body_length);
+        self.book(pedestrian_id, &path, start, speed, body_length);
+        start
+    }
+
+    fn book(&mut self, id: &str, path: &[PathZone], entry_time: SystemTime, speed: f64, body_length: f64) {
+        for (zone, reservation) in generate_zone_reservations(id, path, entry_time, speed, body_length) {
             if let Some(zone_res_list) = self.grid.get_mut(&zone) {
                 zone_res_list.push(reservation);
             }
         }
+    }
+
+    /// Replace the zones held for a car that books nothing, e.g. one with a human
+    /// driver, with windows inferred from where it was seen: `distance` px short
+    /// of the start of `path` (negative once past it), going no faster than
+    /// `fastest` and no slower than `slowest`. Zones it has cleared are released.
+    pub fn block_observed(&mut self, car_id: &str, path: &[PathZone], distance: f64, fastest: f64, slowest: f64) {
+        self.cancel(car_id);
+        let body_length = CAR_HEIGHT_PX as f64 + SAFE_DISTANCE_PX;
+
+        for zone in path {
+            let to_zone = distance + zone.reach;
+            let past_zone = distance + zone.leave + body_length;
+            if past_zone <= 0.0 {
+                continue;
+            }
+            let reservation = ZoneReservation {
+                car_id: car_id.to_string(),
+                time_in: self.now + Duration::from_secs_f64(to_zone.max(0.0) / fastest),
+                time_out: self.now + Duration::from_secs_f64(past_zone / slowest),
+            };
+            if let Some(res_list) = self.grid.get_mut(&zone.zone) {
+                res_list.push(reservation);
+            }
+        }
+    }
+
+    /// Whether a window booked by `car_id` overlaps someone else's, as happens
+    /// when zones are blocked for a car that was seen rather than booked.
+    pub fn has_conflict(&self, car_id: &str) -> bool {
+        self.grid.values().any(|res_list| {
+            res_list.iter().filter(|res| res.car_id == car_id).any(|own| {
+                res_list
+                    .iter()
+                    .any(|other| other.car_id != car_id && other.time_in < own.time_out && own.time_in < other.time_out)
+            })
+        })
+    }
+
+    /// Windows held by a car, in the order it reaches its zones.
+    pub fn windows_of(&self, car_id: &str) -> Vec<ZoneSlot> {
+        let mut slots: Vec<ZoneSlot> = self
+            .grid
+            .iter()
+            .flat_map(|(&zone, res_list)| {
+                res_list
+                    .iter()
+                    .filter(|res| res.car_id == car_id)
+                    .map(move |res| ZoneSlot { zone, time_in: res.time_in, time_out: res.time_out })
+            })
+            .collect();
+        slots.sort_by_key(|slot| slot.time_in);
+        slots
+    }
 
-        entry_time
+    pub fn holds_zones(&self, car_id: &str) -> bool {
+        self.grid.values().flatten().any(|res| res.car_id == car_id)
     }
-    
-    pub fn update(&mut self) {
-        let now = SystemTime::now();
+
+    /// Drop every reservation held by a car, e.g. before it books a new slot.
+    pub fn cancel(&mut self, car_id: &str) {
+        for res_list in self.grid.values_mut() {
+            res_list.retain(|res| res.car_id != car_id);
+        }
+    }
+
+    pub fn update(&mut self, now: SystemTime) {
+        self.now = now;
 
         for res_list in self.grid.values_mut() {
             res_list.retain(|res| res.time_out > now);
         }
     }
 
-    pub fn draw(&self, canvas: &mut Canvas<Window>) -> Result<(), String> {
-        let rect = Rect::new(INTERSECTION_START_X - 50, INTERSECTION_START_Y - 50, INTERSECTION_RECT_SIZE as u32, INTERSECTION_RECT_SIZE as u32);
+    pub fn reservation_count(&self) -> usize {
+        self.grid.values().map(Vec::len).sum()
+    }
+
+    /// Number of zones with any reservation, and of zones held right now.
+    pub fn zone_counts(&self, now: SystemTime) -> (usize, usize) {
+        let reserved = self.grid.values().filter(|res_list| !res_list.is_empty()).count();
+        let occupied = self
+            .grid
+            .values()
+            .filter(|res_list| res_list.iter().any(|res| res.time_in <= now && res.time_out > now))
+            .count();
+        (reserved, occupied)
+    }
+
+    pub fn draw<T: RenderTarget>(&self, canvas: &mut Canvas<T>) -> Result<(), String> {
         canvas.set_draw_color(INTERSECTION_COLOR);
-        canvas.draw_rect(rect)?;
+        canvas.draw_rect(self.layout.box_rect())?;
+        if let Some(ring) = &self.layout.roundabout {
+            canvas.set_draw_color(ROUNDABOUT_ISLAND_COLOR);
+            canvas.draw_lines(&ring.island_spans()[..])?;
+        }
 
-        let zone_size = ZONE_LENGTH_PX as i32;
-        let start_x = INTERSECTION_START_X;
-        let start_y = INTERSECTION_START_Y;
-        let now = SystemTime::now();
+        let now = self.now;
 
         canvas.set_draw_color(Color::RGB(0, 0, 0));
 
-        for y in 0..4 {
-            for x in 0..4 {
-                let rect = Rect::new(
-                    start_x + x as i32 * zone_size,
-                    start_y + y as i32 * zone_size,
-                    zone_size as u32,
-                    zone_size as u32,
-                );
-
-                let reservations = self.grid.get(&(y, x)).unwrap();
-                let mut has_active = false;
-                let mut has_any = false;
-
-                for res in reservations {
-                    has_any = true;
-                    if res.time_in <= now && res.time_out > now {
-                        has_active = true;
-                        break;
-                    }
-                }
+        for zone in self.layout.zones() {
+            let outline = self.layout.zone_outline(zone);
 
-                let color = if has_active {
-                    Color::RGB(160, 32, 240) // Purple (active)
-                } else if has_any {
-                    Color::RED // Red (reserved, but inactive)
-                } else {
-                    Color::GREEN // Green (free)
-                };
+            let reservations = self.grid.get(&zone).unwrap();
+            let mut has_active = false;
+            let mut has_any = false;
 
-                canvas.set_draw_color(color);
-                canvas.draw_rect(rect)?;
+            for res in reservations {
+                has_any = true;
+                if res.time_in <= now && res.time_out > now {
+                    has_active = true;
+                    break;
+                }
             }
+
+            let color = if has_active {
+                Color::RGB(160, 32, 240) // Purple (active)
+            } else if has_any {
+                Color::RED // Red (reserved, but inactive)
+            } else {
+                Color::GREEN // Green (free)
+            };
+
+            canvas.set_draw_color(color);
+            canvas.draw_lines(&outline[..])?;
         }
 
         Ok(())
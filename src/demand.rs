@@ -0,0 +1,126 @@
+use crate::consts::BASE_DELTA_TIME;
+use crate::intersection::Direction;
+use crate::utils::route_between;
+use rand::prelude::*;
+use rand::rng;
+use std::fs;
+use std::time::Duration;
+
+/// Headings a matrix has rows and columns for.
+const HEADINGS: usize = Direction::ALL.len();
+
+/// Origin-destination demand between approaches.
+/// `weights[o][d]` is the relative number of cars heading `o` that leave heading `d`,
+/// with rows and columns in `Direction::ALL` order. U-turns are never generated.
+#[derive(Clone)]
+pub struct OdMatrix {
+    weights: [[f64; HEADINGS]; HEADINGS],
+}
+
+impl OdMatrix {
+    /// Every turn from every approach is equally likely.
+    pub fn uniform() -> Self {
+        Self::from_weights([[1.0; HEADINGS]; HEADINGS])
+    }
+
+    fn from_weights(mut weights: [[f64; HEADINGS]; HEADINGS]) -> Self {
+        for (o, row) in weights.iter_mut().enumerate() {
+            for (d, weight) in row.iter_mut().enumerate() {
+                if route_between(Direction::ALL[o], Direction::ALL[d]).is_none() {
+                    *weight = 0.0;
+                }
+            }
+        }
+        OdMatrix { weights }
+    }
+
+    /// Load a matrix from a text file: four rows of four non-negative numbers
+    /// (origins and destinations in N S E W order), or eight rows of eight with
+    /// NE SE SW NW after those; `#` starts a comment.
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let rows: Vec<&str> = text
+            .lines()
+            .map(|line| line.split('#').next().unwrap_or("").trim())
+            .filter(|line| !line.is_empty())
+            .collect();
+
+        let size = rows.len();
+        if size != 4 && size != HEADINGS {
+            return Err(format!("expected 4 or {} rows, found {}", HEADINGS, size));
+        }
+
+        let mut weights = [[0.0; HEADINGS]; HEADINGS];
+        for (o, row) in rows.iter().enumerate() {
+            let values: Vec<f64> = row
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|v| !v.is_empty())
+                .map(|v| v.parse::<f64>().map_err(|e| format!("row {}: {}", o + 1, e)))
+                .collect::<Result<_, _>>()?;
+
+            if values.len() != size || values.iter().any(|v| *v < 0.0) {
+                return Err(format!("row {}: expected {} non-negative values", o + 1, size));
+            }
+            weights[o][..size].copy_from_slice(&values);
+        }
+
+        Ok(Self::from_weights(weights))
+    }
+
+    /// Same weights with every movement `allowed` rejects taken out, e.g. turns
+    /// into an arm a T-junction does not have.
+    pub fn restricted(&self, allowed: impl Fn(Direction, Direction) -> bool) -> Self {
+        let mut weights = self.weights;
+        for (o, row) in weights.iter_mut().enumerate() {
+            for (d, weight) in row.iter_mut().enumerate() {
+                if !allowed(Direction::ALL[o], Direction::ALL[d]) {
+                    *weight = 0.0;
+                }
+            }
+        }
+        OdMatrix { weights }
+    }
+
+    /// Draw an origin and destination for a new car.
+    pub fn sample(&self) -> Option<(Direction, Direction)> {
+        let pairs: Vec<(usize, usize)> = (0..HEADINGS).flat_map(|o| (0..HEADINGS).map(move |d| (o, d))).collect();
+        let mut rng = rng();
+        pairs
+            .choose_weighted(&mut rng, |&(o, d)| self.weights[o][d])
+            .ok()
+            .map(|&(o, d)| (Direction::ALL[o], Direction::ALL[d]))
+    }
+
+    /// Draw a destination for a car arriving on the `origin` approach.
+    pub fn sample_destination(&self, origin: Direction) -> Option<Direction> {
+        let row = &self.weights[origin.index()];
+        let mut rng = rng();
+        Direction::ALL
+            .choose_weighted(&mut rng, |d| row[d.index()])
+            .ok()
+            .copied()
+    }
+}
+
+/// Releases one car from the OD matrix every `interval` of simulated time,
+/// so a run can generate traffic without anyone at the keyboard.
+pub struct FixedArrivals {
+    interval: Duration,
+    next: Duration,
+}
+
+impl FixedArrivals {
+    pub fn new(interval: Duration) -> Self {
+        FixedArrivals { interval: interval.max(BASE_DELTA_TIME), next: Duration::ZERO }
+    }
+
+    /// Number of arrivals that fall due by simulated time `elapsed`.
+    pub fn due(&mut self, elapsed: Duration) -> usize {
+        let mut count = 0;
+        while self.next <= elapsed {
+            self.next += self.interval;
+            count += 1;
+        }
+        count
+    }
+}
@@ -0,0 +1,195 @@
+use crate::consts::*;
+use crate::crossing_manager::{ZoneIndex, ZoneReservation};
+use crate::intersection::Intersection;
+use sdl2::VideoSubsystem;
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::Canvas;
+use sdl2::ttf::Font;
+use sdl2::video::Window;
+use std::collections::HashMap;
+use std::time::SystemTime;
+
+/// Car found inside a zone cell while holding no active window for it.
+struct Violation {
+    zone: ZoneIndex,
+    car_id: String,
+    from: SystemTime,
+    to: SystemTime,
+}
+
+/// Second window charting zone reservations over time: one row per zone,
+/// `GANTT_PAST` behind to `GANTT_FUTURE` ahead of the current simulation time.
+pub struct GanttView<'ttf> {
+    canvas: Canvas<Window>,
+    font: Font<'ttf, 'static>,
+    /// Reservations seen so far; the manager prunes expired ones, the chart keeps
+    /// them until they scroll off the left edge.
+    history: HashMap<ZoneIndex, Vec<ZoneReservation>>,
+    violations: Vec<Violation>,
+}
+
+impl<'ttf> GanttView<'ttf> {
+    pub fn open(video: &VideoSubsystem, font: Font<'ttf, 'static>) -> Result<Self, String> {
+        let window = video
+            .window("Zone reservations", GANTT_WINDOW_WIDTH, GANTT_WINDOW_HEIGHT)
+            .build()
+            .map_err(|e| e.to_string())?;
+        let canvas = window.into_canvas().build().map_err(|e| e.to_string())?;
+
+        Ok(GanttView { canvas, font, history: HashMap::new(), violations: Vec::new() })
+    }
+
+    pub fn window_id(&self) -> u32 {
+        self.canvas.window().id()
+    }
+
+    fn record(&mut self, intersection: &Intersection) {
+        let now = intersection.now;
+        let view_start = now - GANTT_PAST;
+
+        for (zone, live) in &intersection.crossing_manager.grid {
+            let history = self.history.entry(*zone).or_default();
+            // Keep windows that have started; future ones not in the grid were cancelled
+            history.retain(|res| {
+                res.time_out > view_start
+                    && res.time_in <= now
+                    && !live.iter().any(|l| l.car_id == res.car_id && l.time_in == res.time_in)
+            });
+            history.extend(live.iter().cloned());
+        }
+
+        for car in intersection.cars_in.values().flatten() {
+            if car.layout.bypasses_zones(car.route) {
+                continue;
+            }
+            for (&zone, res_list) in &intersection.crossing_manager.grid {
+                let inside = car.overlaps(intersection.layout.zone_rect(zone));
+                let covered = res_list
+                    .iter()
+                    .any(|res| res.car_id == car.id && res.time_in <= now && res.time_out > now);
+                if !inside || covered {
+                    continue;
+                }
+
+                match self
+                    .violations
+                    .iter_mut()
+                    .rev()
+                    .find(|v| v.zone == zone && v.car_id == car.id && v.to + GANTT_VIOLATION_GAP >= now)
+                {
+                    Some(violation) => violation.to = now,
+                    None => {
+                        println!("Car {} is in zone {:?} outside its reservation", car.id, zone);
+                        self.violations.push(Violation { zone, car_id: car.id.clone(), from: now, to: now });
+                    }
+                }
+            }
+        }
+        self.violations.retain(|v| v.to > view_start);
+    }
+
+    pub fn present(&mut self, intersection: &Intersection) -> Result<(), String> {
+        self.record(intersection);
+        let now = intersection.now;
+
+        self.canvas.set_draw_color(BACKGROUND_COLOR);
+        self.canvas.clear();
+
+        let plot_width = GANTT_WINDOW_WIDTH as i32 - GANTT_LABEL_WIDTH_PX - UI_PADDING_X;
+        let span = (GANTT_PAST + GANTT_FUTURE).as_secs_f64();
+        let x_at = |time: SystemTime| {
+            let offset = match time.duration_since(now) {
+                Ok(ahead) => ahead.as_secs_f64(),
+                Err(e) => -e.duration().as_secs_f64(),
+            };
+            let fraction = ((offset + GANTT_PAST.as_secs_f64()) / span).clamp(0.0, 1.0);
+            GANTT_LABEL_WIDTH_PX + (fraction * plot_width as f64).round() as i32
+        };
+        let layout = intersection.layout;
+        // Rows shrink to fit big grids; labels are left out once they no longer fit
+        let zones: Vec<ZoneIndex> = layout.zones().collect();
+        let row_height = GANTT_ROW_HEIGHT_PX.min((GANTT_WINDOW_HEIGHT as i32 - GANTT_HEADER_HEIGHT_PX) / zones.len().max(1) as i32);
+        let labelled = row_height == GANTT_ROW_HEIGHT_PX;
+        let row_y = |zone: ZoneIndex| {
+            GANTT_HEADER_HEIGHT_PX + zones.iter().position(|&z| z == zone).unwrap_or(0) as i32 * row_height
+        };
+        let plot_height = zones.len() as i32 * row_height;
+
+        // Whole-second grid lines, labelled relative to now
+        let past = GANTT_PAST.as_secs() as i64;
+        for second in -past..=GANTT_FUTURE.as_secs() as i64 {
+            let x = GANTT_LABEL_WIDTH_PX + ((second + past) as f64 / span * plot_width as f64).round() as i32;
+            self.canvas.set_draw_color(GANTT_GRID_COLOR);
+            self.canvas.draw_line((x, GANTT_HEADER_HEIGHT_PX), (x, GANTT_HEADER_HEIGHT_PX + plot_height))?;
+            let label = if second == 0 { "now".to_string() } else { format!("{:+} s", second) };
+            self.draw_text(&label, Color::WHITE, x + 2, UI_PADDING_Y / 2, GANTT_WINDOW_WIDTH)?;
+        }
+
+        for &zone in &zones {
+            let y = row_y(zone);
+            self.canvas.set_draw_color(GANTT_GRID_COLOR);
+            self.canvas.draw_line((0, y), (GANTT_WINDOW_WIDTH as i32, y))?;
+            if labelled {
+                self.draw_text(&format!("{:?}", zone), Color::WHITE, UI_PADDING_X / 2, y + 6, GANTT_LABEL_WIDTH_PX as u32)?;
+            }
+
+            let bars = self.history.get(&zone).cloned().unwrap_or_default();
+            for res in &bars {
+                let overlaps = bars.iter().any(|other| {
+                    other.car_id != res.car_id && other.time_in < res.time_out && res.time_in < other.time_out
+                });
+                let color = if overlaps {
+                    GANTT_CONFLICT_COLOR
+                } else if res.time_in <= now && res.time_out > now {
+                    GANTT_ACTIVE_COLOR
+                } else {
+                    GANTT_BAR_COLOR
+                };
+
+                let (x_in, x_out) = (x_at(res.time_in), x_at(res.time_out));
+                if x_out <= x_in {
+                    continue;
+                }
+                let bar = Rect::new(x_in, y + row_height / 10, (x_out - x_in) as u32, (row_height - row_height / 5).max(1) as u32);
+                self.canvas.set_draw_color(color);
+                self.canvas.fill_rect(bar)?;
+                self.canvas.set_draw_color(BACKGROUND_COLOR);
+                self.canvas.draw_rect(bar)?;
+                if labelled {
+                    self.draw_text(&res.car_id, Color::WHITE, bar.x() + 3, y + 6, bar.width().saturating_sub(6))?;
+                }
+            }
+        }
+
+        self.canvas.set_draw_color(GANTT_CONFLICT_COLOR);
+        for violation in &self.violations {
+            let y = row_y(violation.zone);
+            let (x_from, x_to) = (x_at(violation.from), x_at(violation.to));
+            let marker = Rect::new(x_from, y + 1, (x_to - x_from).max(2) as u32, (row_height - 2).max(3) as u32);
+            self.canvas.draw_rect(marker)?;
+            self.canvas.draw_rect(Rect::new(marker.x() + 1, marker.y() + 1, marker.width().saturating_sub(2).max(1), marker.height() - 2))?;
+        }
+
+        let now_x = x_at(now);
+        self.canvas.set_draw_color(Color::WHITE);
+        self.canvas.draw_line((now_x, GANTT_HEADER_HEIGHT_PX), (now_x, GANTT_HEADER_HEIGHT_PX + plot_height))?;
+
+        self.canvas.present();
+        Ok(())
+    }
+
+    /// Draw a single line of text, cut off at `max_width` px.
+    fn draw_text(&mut self, text: &str, color: Color, x: i32, y: i32, max_width: u32) -> Result<(), String> {
+        if max_width == 0 || text.is_empty() {
+            return Ok(());
+        }
+        let surface = self.font.render(text).blended(color).map_err(|e| e.to_string())?;
+        let texture_creator = self.canvas.texture_creator();
+        let texture = texture_creator.create_texture_from_surface(&surface).map_err(|e| e.to_string())?;
+
+        let width = surface.width().min(max_width);
+        let clip = Rect::new(0, 0, width, surface.height());
+        self.canvas.copy(&texture, Some(clip), Some(Rect::new(x, y, width, surface.height())))
+    }
+}
@@ -0,0 +1,220 @@
+use crate::clock::SimClock;
+use crate::consts::*;
+use crate::inspector::{self, Selection};
+use crate::intersection::{Intersection, Lane};
+use crate::network::Network;
+use sdl2::pixels::Color;
+use sdl2::rect::{Point, Rect};
+use sdl2::render::{BlendMode, Canvas, RenderTarget, TextureCreator};
+use sdl2::ttf::Font;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Live statistics drawn over the top-left corner of the simulation window,
+/// and details of the clicked car or zone over the top-right corner.
+pub struct Hud<'ttf> {
+    pub visible: bool,
+    /// Car or zone clicked on, described in a second panel.
+    pub selection: Option<Selection>,
+    font: Font<'ttf, 'static>,
+    /// Vehicles crossed per bucket of simulated time, oldest first.
+    throughput: VecDeque<usize>,
+    bucket_start: Duration,
+    crossed_at_bucket_start: usize,
+    frame_intervals: VecDeque<f64>,
+    last_frame: Instant,
+    clock_label: String,
+}
+
+impl<'ttf> Hud<'ttf> {
+    pub fn new(font: Font<'ttf, 'static>) -> Self {
+        Hud {
+            visible: true,
+            selection: None,
+            font,
+            throughput: VecDeque::with_capacity(HUD_SPARKLINE_BUCKETS),
+            bucket_start: Duration::ZERO,
+            crossed_at_bucket_start: 0,
+            frame_intervals: VecDeque::with_capacity(HUD_FPS_WINDOW),
+            last_frame: Instant::now(),
+            clock_label: String::new(),
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+    }
+
+    /// Called once per frame; keeps the FPS window and throughput buckets current
+    /// even while the overlay is hidden so they are meaningful when it reappears.
+    pub fn record(&mut self, network: &Network, clock: &SimClock) {
+        let now = Instant::now();
+        self.clock_label = clock.label();
+
+        if self.frame_intervals.len() == HUD_FPS_WINDOW {
+            self.frame_intervals.pop_front();
+        }
+        self.frame_intervals.push_back((now - self.last_frame).as_secs_f64());
+        self.last_frame = now;
+
+        let crossed = network.crossed();
+        let sim_time = network.elapsed();
+        while sim_time - self.bucket_start >= HUD_SPARKLINE_BUCKET {
+            if self.throughput.len() == HUD_SPARKLINE_BUCKETS {
+                self.throughput.pop_front();
+            }
+            self.throughput.push_back(crossed.saturating_sub(self.crossed_at_bucket_start));
+            self.crossed_at_bucket_start = crossed;
+            self.bucket_start += HUD_SPARKLINE_BUCKET;
+        }
+    }
+
+    fn fps(&self) -> f64 {
+        let total: f64 = self.frame_intervals.iter().sum();
+        if total > 0.0 { self.frame_intervals.len() as f64 / total } else { 0.0 }
+    }
+
+    fn text(&self, network: &Network) -> String {
+        let intersection = network.focused();
+        let cars = || intersection.cars_in.values().flatten();
+        let (reserved, occupied) = intersection.crossing_manager.zone_counts(intersection.now);
+
+        let mut text = String::new();
+        if network.nodes.len() > 1 {
+            text.push_str(&format!(
+                "Intersection {} ({} of {})\n",
+                network.names[network.focus],
+                network.focus + 1,
+                network.nodes.len()
+            ));
+        }
+        text.push_str(&format!(
+            "Sim time: {:.1} s ({})    FPS: {:.0}\n\
+            Crossed: {}    On road: {}    In box: {}\n\
+            Collisions: {}    Near misses: {}\n\
+            Zones reserved: {}    held: {}\n\
+            Lanes curb/middle/median + upstream\n",
+            intersection.elapsed().as_secs_f64(),
+            self.clock_label,
+            self.fps(),
+            intersection.cars_out.len(),
+            cars().count(),
+            cars().filter(|car| car.in_intersection).count(),
+            intersection.collision_count,
+            intersection.near_miss,
+            reserved,
+            occupied,
+        ));
+        for dir in intersection.layout.approaches() {
+            let lanes: Vec<String> = Lane::ALL
+                .iter()
+                .map(|&lane| intersection.cars_in[&(dir, lane)].len().to_string())
+                .collect();
+            text.push_str(&format!(
+                "{:?}: {}  +{}\n",
+                dir,
+                lanes.join("/"),
+                intersection.spawn_queues[&dir].waiting.len()
+            ));
+        }
+        text.push_str(&format!(
+            "Throughput, last {} s (peak {}/s)",
+            HUD_SPARKLINE_BUCKETS,
+            self.throughput.iter().max().copied().unwrap_or(0)
+        ));
+        text
+    }
+
+    pub fn draw<T: RenderTarget>(
+        &self, canvas: &mut Canvas<T>, texture_creator: &TextureCreator<T::Context>, network: &Network,
+    ) -> Result<(), String> {
+        if self.visible {
+            self.draw_statistics(canvas, texture_creator, network)?;
+        }
+        if let Some(selection) = &self.selection {
+            let x = SIMULATION_WINDOW_WIDTH as i32 - HUD_INSPECTOR_WIDTH_PX as i32 - UI_PADDING_X;
+            let text = inspector::describe(network.focused(), selection);
+            self.draw_panel(canvas, texture_creator, &text, Point::new(x, UI_PADDING_Y), HUD_INSPECTOR_WIDTH_PX, 0)?;
+        }
+        Ok(())
+    }
+
+    /// Render `text` on a translucent backdrop with its top-left corner at `origin`,
+    /// leaving `extra_height` px of backdrop below it; returns where the text went.
+    fn draw_panel<T: RenderTarget>(
+        &self, canvas: &mut Canvas<T>, texture_creator: &TextureCreator<T::Context>,
+        text: &str, origin: Point, width: u32, extra_height: u32,
+    ) -> Result<Rect, String> {
+        let (x, y) = (origin.x(), origin.y());
+        let surface = self
+            .font
+            .render(text)
+            .blended_wrapped(Color::WHITE, width)
+            .map_err(|e| e.to_string())?;
+        let texture = texture_creator
+            .create_texture_from_surface(&surface)
+            .map_err(|e| e.to_string())?;
+
+        let panel = Rect::new(
+            x - UI_PADDING_X / 2,
+            y - UI_PADDING_Y / 2,
+            width + UI_PADDING_X as u32,
+            surface.height() + extra_height + UI_PADDING_Y as u32,
+        );
+        canvas.set_blend_mode(BlendMode::Blend);
+        canvas.set_draw_color(HUD_BACKGROUND_COLOR);
+        canvas.fill_rect(panel)?;
+        canvas.set_blend_mode(BlendMode::None);
+
+        let target = Rect::new(x, y, surface.width(), surface.height());
+        canvas.copy(&texture, None, Some(target))?;
+        Ok(target)
+    }
+
+    fn draw_statistics<T: RenderTarget>(
+        &self, canvas: &mut Canvas<T>, texture_creator: &TextureCreator<T::Context>, network: &Network,
+    ) -> Result<(), String> {
+        let extra_height = HUD_SPARKLINE_HEIGHT_PX + UI_PADDING_Y as u32;
+        let text = self.text(network);
+        let text = self.draw_panel(canvas, texture_creator, &text, Point::new(UI_PADDING_X, UI_PADDING_Y), HUD_WIDTH_PX, extra_height)?;
+        let sparkline_top = text.bottom() + UI_PADDING_Y;
+
+        // One bar per bucket, scaled to the busiest bucket on screen
+        let peak = self.throughput.iter().max().copied().unwrap_or(0).max(1);
+        let bar_width = (HUD_WIDTH_PX / HUD_SPARKLINE_BUCKETS as u32).max(1);
+        let offset = HUD_SPARKLINE_BUCKETS - self.throughput.len();
+        canvas.set_draw_color(HUD_SPARKLINE_COLOR);
+        for (i, &count) in self.throughput.iter().enumerate() {
+            let height = (count as u32 * HUD_SPARKLINE_HEIGHT_PX / peak as u32).max(1);
+            canvas.fill_rect(Rect::new(
+                UI_PADDING_X + ((offset + i) as u32 * bar_width) as i32,
+                sparkline_top + (HUD_SPARKLINE_HEIGHT_PX - height) as i32,
+                bar_width,
+                height,
+            ))?;
+        }
+        Ok(())
+    }
+
+    /// Outline and label the selection; drawn in the intersection's own coordinates.
+    pub fn draw_selection<T: RenderTarget>(
+        &self, canvas: &mut Canvas<T>, texture_creator: &TextureCreator<T::Context>, intersection: &Intersection,
+    ) -> Result<(), String> {
+        let Some(selection) = &self.selection else {
+            return Ok(());
+        };
+        if let Some(outline) = inspector::highlight(intersection, selection) {
+            canvas.set_draw_color(HUD_SELECTION_COLOR);
+            canvas.draw_rect(outline)?;
+            canvas.draw_rect(Rect::new(outline.x() - 1, outline.y() - 1, outline.width() + 2, outline.height() + 2))?;
+
+            if let Selection::Car(id) = selection {
+                let label = self.font.render(id).blended(HUD_SELECTION_COLOR).map_err(|e| e.to_string())?;
+                let texture = texture_creator.create_texture_from_surface(&label).map_err(|e| e.to_string())?;
+                let target = Rect::new(outline.x(), outline.y() - label.height() as i32, label.width(), label.height());
+                canvas.copy(&texture, None, Some(target))?;
+            }
+        }
+        Ok(())
+    }
+}
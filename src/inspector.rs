@@ -0,0 +1,124 @@
+use crate::car::{Car, DriverType};
+use crate::crossing_manager::ZoneIndex;
+use crate::intersection::Intersection;
+use sdl2::rect::{Point, Rect};
+
+/// What the mouse last picked in the simulation window.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Selection {
+    Car(String),
+    Zone(ZoneIndex),
+}
+
+/// Car under the cursor if any, otherwise the zone cell under it.
+pub fn pick(intersection: &Intersection, x: i32, y: i32) -> Option<Selection> {
+    let point = Point::new(x, y);
+
+    if let Some(car) = intersection
+        .cars_in
+        .values()
+        .flatten()
+        .find(|car| car.bounding_box().contains_point(point))
+    {
+        return Some(Selection::Car(car.id.clone()));
+    }
+
+    intersection.layout.zone_at(x, y).map(Selection::Zone)
+}
+
+fn find_car<'a>(intersection: &'a Intersection, id: &str) -> Option<&'a Car<'a>> {
+    intersection
+        .cars_in
+        .values()
+        .flatten()
+        .chain(intersection.cars_out.iter())
+        .find(|car| car.id == id)
+}
+
+/// Outline to draw around the selection, if it is still on screen.
+pub fn highlight(intersection: &Intersection, selection: &Selection) -> Option<Rect> {
+    match selection {
+        Selection::Car(id) => find_car(intersection, id).filter(|car| !car.exited).map(Car::bounding_box),
+        Selection::Zone(zone) => Some(intersection.layout.zone_rect(*zone)),
+    }
+}
+
+/// Multi-line description of the selection; times are simulated seconds.
+pub fn describe(intersection: &Intersection, selection: &Selection) -> String {
+    let yes_no = |b: bool| if b { "yes" } else { "no" };
+    let window = |car_id: &str, time_in, time_out| {
+        let now = intersection.now;
+        let marker = if time_in <= now && time_out > now { "  <- now" } else { "" };
+        format!(
+            "{} {:.2}-{:.2} s{}\n",
+            car_id,
+            intersection.sim_seconds(time_in),
+            intersection.sim_seconds(time_out),
+            marker
+        )
+    };
+
+    match selection {
+        Selection::Car(id) => {
+            let Some(car) = find_car(intersection, id) else {
+                return format!("Car {}\nno longer tracked", id);
+            };
+            let actual_entry = car
+                .actual_entry_time
+                .map(|t| format!("{:.2} s", intersection.sim_seconds(t)))
+                .unwrap_or("-".to_string());
+
+            let mut text = format!(
+                "Car {}\n\
+                From {:?}, heading {:?}, {:?}\n\
+                Lane {:?} (target {:?})\n\
+                Driver {:?}    Waiting to go: {}\n\
+                Class {:?}    Asked to enter {:.2} s\n\
+                V2I: {}\n\
+                Speed {:.0} px/s, crossing at {:.0}\n\
+                Entry scheduled {:.2} s, actual {}\n\
+                Braking: {}    Collided: {}\n\
+                Zone windows:\n",
+                car.id,
+                car.origin,
+                car.direction,
+                car.route,
+                car.lane,
+                car.target_lane,
+                car.driver,
+                yes_no(!car.in_intersection && (car.held || (car.driver == DriverType::Human && !car.cleared))),
+                car.class,
+                intersection.sim_seconds(car.time_requested),
+                if car.driver == DriverType::Human { "-".to_string() } else { car.v2i.status() },
+                car.speed,
+                car.crossing_speed,
+                intersection.sim_seconds(car.entry_time),
+                actual_entry,
+                yes_no(car.brake),
+                yes_no(car.collided),
+            );
+
+            let windows = intersection.crossing_manager.windows_of(&car.id);
+            if windows.is_empty() {
+                text.push_str("none left\n");
+            }
+            for slot in windows {
+                text.push_str(&window(&format!("{:?}", slot.zone), slot.time_in, slot.time_out));
+            }
+            text
+        }
+        Selection::Zone(zone) => {
+            let mut reservations = intersection.crossing_manager.grid[zone].clone();
+            reservations.sort_by_key(|res| res.time_in);
+
+            let mut text = format!("Zone {:?}\n", zone);
+            if reservations.is_empty() {
+                text.push_str("no reservations\n");
+            }
+            for res in reservations {
+                text.push_str(&window(&res.car_id, res.time_in, res.time_out));
+            }
+            text
+        }
+    }
+}
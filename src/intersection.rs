@@ -1,119 +1,555 @@
-use crate::car::Car;
+use crate::car::{Car, DriverType, Leader, Spawn, VehicleClass};
 use crate::cars_id::CarIdGenerator;
-use crate::crossing_manager::CrossingManager;
+use crate::crossing_manager::{Approach, CrossingManager, SlotOffer, ZoneIndex};
+use crate::demand::OdMatrix;
+use crate::map::Layout;
+use crate::metrics::{DelaySample, Movement, MovementStats, movement_report, movements, percentile};
+use crate::pedestrian::Pedestrian;
+use crate::spawn_queue::{QueuedCar, SpawnQueue};
+use crate::speed_profile::{SpeedProfile, earliest_arrival, plan_arrival};
 use crate::utils::*;
+use crate::v2i::{Client, Message, Server, SessionState};
 use std::collections::HashMap;
 
+use rand::prelude::*;
+use rand::rng;
 use chrono::{DateTime, Local};
-use sdl2::render::Texture;
-use std::time::{SystemTime};
+use serde::{Deserialize, Serialize};
+use sdl2::rect::Rect;
+use sdl2::render::{Canvas, RenderTarget, Texture};
+use std::time::{Duration, SystemTime};
 
 use crate::consts::*;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Direction {
     North,
     South,
     East,
     West,
+    NorthEast,
+    SouthEast,
+    SouthWest,
+    NorthWest,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+impl Direction {
+    /// Headings along the screen axes first, then the diagonal ones.
+    pub const ALL: [Direction; 8] = [
+        Direction::North,
+        Direction::South,
+        Direction::East,
+        Direction::West,
+        Direction::NorthEast,
+        Direction::SouthEast,
+        Direction::SouthWest,
+        Direction::NorthWest,
+    ];
+
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    /// Eighths of a full turn clockwise on screen from north.
+    fn eighths(self) -> i32 {
+        match self {
+            Direction::North => 0,
+            Direction::NorthEast => 1,
+            Direction::East => 2,
+            Direction::SouthEast => 3,
+            Direction::South => 4,
+            Direction::SouthWest => 5,
+            Direction::West => 6,
+            Direction::NorthWest => 7,
+        }
+    }
+
+    /// Heading after turning `eighths` eighths of a full turn clockwise on screen.
+    pub fn turned(self, eighths: i32) -> Direction {
+        let target = (self.eighths() + eighths).rem_euclid(8);
+        Direction::ALL.into_iter().find(|heading| heading.eighths() == target).unwrap()
+    }
+
+    /// Eighths of a full turn clockwise on screen from this heading to `other`, from 0 to 7.
+    pub fn eighths_to(self, other: Direction) -> i32 {
+        (other.eighths() - self.eighths()).rem_euclid(8)
+    }
+
+    pub fn opposite(self) -> Direction {
+        self.turned(4)
+    }
+
+    /// One step of travel on screen (y down), a pixel along each axis it moves on.
+    pub fn vector(self) -> (i32, i32) {
+        match self {
+            Direction::North => (0, -1),
+            Direction::South => (0, 1),
+            Direction::East => (1, 0),
+            Direction::West => (-1, 0),
+            Direction::NorthEast => (1, -1),
+            Direction::SouthEast => (1, 1),
+            Direction::SouthWest => (-1, 1),
+            Direction::NorthWest => (-1, -1),
+        }
+    }
+
+    /// Length on screen of one step of `vector`.
+    pub fn step_length(self) -> f64 {
+        let (dx, dy) = self.vector();
+        (dx as f64).hypot(dy as f64)
+    }
+
+    /// Direction of travel on screen as a unit vector. Layout geometry is worked
+    /// out from this rather than from the name of the heading.
+    pub fn unit(self) -> (f64, f64) {
+        let (dx, dy) = self.vector();
+        (dx as f64 / self.step_length(), dy as f64 / self.step_length())
+    }
+
+    /// Whether travel runs up or down the screen.
+    pub fn is_vertical(self) -> bool {
+        self.vector().0 == 0
+    }
+
+    /// Whether travel runs at an angle to both screen axes.
+    pub fn is_diagonal(self) -> bool {
+        let (dx, dy) = self.vector();
+        dx != 0 && dy != 0
+    }
+
+    /// Heading closest to travel along `(dx, dy)`.
+    pub fn nearest(dx: f64, dy: f64) -> Direction {
+        let dot = |heading: &Direction| {
+            let (x, y) = heading.unit();
+            x * dx + y * dy
+        };
+        Direction::ALL.into_iter().max_by(|a, b| dot(a).total_cmp(&dot(b))).unwrap()
+    }
+}
+
+/// Turn made at an intersection. Bears swing an eighth of a full turn, plain
+/// turns a quarter and sharp turns three eighths; only intersections with
+/// diagonal arms have bears and sharp turns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Route {
     Left,
     Right,
     Straight,
+    BearLeft,
+    BearRight,
+    SharpLeft,
+    SharpRight,
+}
+
+impl Route {
+    /// From the sharpest turn left to the sharpest turn right.
+    pub const ALL: [Route; 7] = [
+        Route::SharpLeft,
+        Route::Left,
+        Route::BearLeft,
+        Route::Straight,
+        Route::BearRight,
+        Route::Right,
+        Route::SharpRight,
+    ];
+
+    /// Eighths of a full turn clockwise on screen, negative to the left.
+    pub fn eighths(self) -> i32 {
+        match self {
+            Route::SharpLeft => -3,
+            Route::Left => -2,
+            Route::BearLeft => -1,
+            Route::Straight => 0,
+            Route::BearRight => 1,
+            Route::Right => 2,
+            Route::SharpRight => 3,
+        }
+    }
+
+    /// Plain turn to the same side, or straight on. A bear or sharp turn is
+    /// driven from and into the same lanes as it, at the same speeds.
+    pub fn side(self) -> Route {
+        match self.eighths().signum() {
+            1 => Route::Right,
+            -1 => Route::Left,
+            _ => Route::Straight,
+        }
+    }
+}
+
+/// Lanes of an approach, from the kerb towards the centre line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Lane {
+    Curb,
+    Middle,
+    Median,
+}
+
+impl Lane {
+    pub const ALL: [Lane; 3] = [Lane::Curb, Lane::Middle, Lane::Median];
+
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    /// Next lane over when heading for `target`.
+    pub fn towards(self, target: Lane) -> Lane {
+        match self.index().cmp(&target.index()) {
+            std::cmp::Ordering::Less => Lane::ALL[self.index() + 1],
+            std::cmp::Ordering::Greater => Lane::ALL[self.index() - 1],
+            std::cmp::Ordering::Equal => self,
+        }
+    }
+}
+
+/// When and how fast a car is expected to reach the next intersection, sent as
+/// it enters this one so the next one can book its slot ahead.
+pub struct ArrivalForecast {
+    pub car_id: String,
+    /// Heading the car arrives with, i.e. the approach it will use.
+    pub heading: Direction,
+    /// Heading it leaves the next intersection with.
+    pub destination: Direction,
+    pub at: SystemTime,
+    pub speed: f64,
+    pub class: VehicleClass,
 }
 
 pub struct Intersection<'a> {
+    pub layout: &'a Layout,
     pub car_textures: HashMap<Route, &'a Texture<'a>>,
-    pub cars_in: HashMap<(Direction, Route), Vec<Car<'a>>>,
+    pub cars_in: HashMap<(Direction, Lane), Vec<Car<'a>>>,
     pub cars_out: Vec<Car<'a>>,
+    pub od_matrix: OdMatrix,
+    pub spawn_queues: HashMap<Direction, SpawnQueue>,
+    pub movement_stats: HashMap<Movement, MovementStats>,
+    pub started_at: SystemTime,
     pub id_generator: CarIdGenerator,
-    pub crossing_manager: CrossingManager,
+    pub crossing_manager: CrossingManager<'a>,
     pub collision_count: usize,
     pub near_miss: usize,
-    pub last_update: SystemTime,
+    /// Simulation clock; advances only when `update` is called.
+    pub now: SystemTime,
+    /// Cars that left with more of their path to go, by heading, for the network to hand on.
+    pub outbound: Vec<(Direction, QueuedCar)>,
+    /// Arrivals expected at the next intersection, for the network to pass on.
+    pub forecasts: Vec<ArrivalForecast>,
+    /// Slots asked for ahead for cars still upstream, by car id: the lane asked
+    /// for, and the entry time and crossing speed once confirmed.
+    pub prebooked: HashMap<String, (Lane, Option<(SystemTime, f64)>)>,
+    pub prebooks_kept: usize,
+    pub prebooks_missed: usize,
+    /// Pedestrians waiting at a kerb or on a crosswalk, and those across.
+    pub pedestrians: Vec<Pedestrian>,
+    pub pedestrians_out: Vec<Pedestrian>,
+    pub pedestrian_conflicts: usize,
+    pedestrians_spawned: usize,
+    /// Radio links between the autonomous cars and the crossing manager.
+    pub v2i: Server,
 }
 
 impl<'a> Intersection<'a> {
-    pub fn new(car_textures: HashMap<Route, &'a Texture<'a>>) -> Self {
-        use Direction::*;
-        use Route::*;
-
+    pub fn new(
+        layout: &'a Layout,
+        car_textures: HashMap<Route, &'a Texture<'a>>,
+        od_matrix: OdMatrix,
+        queue_capacity: Option<usize>,
+        now: SystemTime,
+    ) -> Self {
         let mut cars_in = HashMap::new();
+        let mut spawn_queues = HashMap::new();
         let id_generator = CarIdGenerator::new();
-        let crossing_manager = CrossingManager::new();
+        let crossing_manager = CrossingManager::new(layout, now);
 
-        for dir in [North, South, East, West] {
-            for route in [Left, Straight, Right] {
-                cars_in.insert((dir, route), Vec::new());
+        for dir in Direction::ALL {
+            for lane in Lane::ALL {
+                cars_in.insert((dir, lane), Vec::new());
             }
+            spawn_queues.insert(dir, SpawnQueue::new(queue_capacity));
         }
         Intersection {
+            layout,
             car_textures,
             cars_in, cars_out: Vec::new(),
+            od_matrix: od_matrix.restricted(|origin, destination| layout.allows(origin, destination)),
+            spawn_queues,
+            movement_stats: movements().map(|m| (m, MovementStats::default())).collect(),
+            started_at: now,
             id_generator, crossing_manager, collision_count: 0, near_miss: 0,
-            last_update: SystemTime::now() }
-    }
-
-    pub fn add_car_in_rnd(&mut self) {
-        let direction = get_rnd_direction();
-        self.add_car_in(direction);
-    }
-
-    pub fn add_car_in(&mut self, direction: Direction) {
-
-        for route in get_rnd_routes() {
-            let (x, y, speed) = get_spawn_position(direction, route);
-            let lane = self.cars_in.get(&(direction, route)).unwrap();
-            let can_spawn = car_spawn_check(lane, direction, x, y, CAR_HEIGHT_PX as i32);
-
-            if can_spawn {
-                let car_id = self.id_generator.get_next(direction, route);
-                let distance_to_entry = if route == Route::Right { ENTRY_DISTANCE_PX_RIGHT as f64 } else { ENTRY_DISTANCE_PX as f64 };
-                let entry_time = self.crossing_manager.latest_available_time(
-                    direction,
-                    route,
-                    distance_to_entry,
-                );
-                self.crossing_manager
-                    .reserve_path(&car_id, direction, route, distance_to_entry);
-
-                let texture = self.car_textures.get(&route).expect("Missing texture for route");
-                
-                let car = Car::new(
-                    car_id.clone(),
-                    x,
-                    y,
-                    CAR_WIDTH_PX,
-                    CAR_HEIGHT_PX,
-                    speed,
-                    texture,
-                    route,
-                    entry_time,
-                    direction,
-                );
-
-                self.cars_in.get_mut(&(direction, route)).unwrap().push(car);
-
-                let datetime: DateTime<Local> = entry_time.into();
-                println!(
-                    "Spawned car {} heading {:?} going {:?} | Entry time: {}",
-                    car_id,
-                    direction,
-                    route,
-                    datetime.format("%H:%M:%S%.3f")
-                );
-                return; // Successfully spawned, exit function
-            }
-        }
-
-        // If reached here, no lane available
+            now, outbound: Vec::new(), forecasts: Vec::new(),
+            prebooked: HashMap::new(), prebooks_kept: 0, prebooks_missed: 0,
+            pedestrians: Vec::new(), pedestrians_out: Vec::new(), pedestrian_conflicts: 0, pedestrians_spawned: 0,
+            v2i: Server::new() }
+    }
+
+    /// New pedestrian at either kerb of a random crosswalk. They wait there for
+    /// the first walk across the manager can fit between the cars already booked.
+    pub fn add_pedestrian(&mut self) {
+        let Some(crosswalk) = self.layout.crosswalks.choose(&mut rng()) else {
+            return;
+        };
+        self.pedestrians_spawned += 1;
+        let id = format!("P-{:04}", self.pedestrians_spawned);
+        let mut pedestrian = Pedestrian::new(id, self.layout, crosswalk, rng().random_bool(0.5), self.now);
+        pedestrian.start_at = self.crossing_manager.reserve_walk(&pedestrian.id, &pedestrian.cells, self.now, PEDESTRIAN_SPEED_PX_PER_SEC);
+
+        let datetime: DateTime<Local> = pedestrian.start_at.into();
         println!(
-            "No free lane found for spawning car in direction {:?}",
-            direction
+            "Pedestrian {} waiting to cross the {:?} approach | Walks at: {}",
+            pedestrian.id,
+            pedestrian.approach,
+            datetime.format("%H:%M:%S%.3f"),
         );
+        self.pedestrians.push(pedestrian);
+    }
+
+    /// Queue a car upstream of its approach; it enters as soon as a lane has room.
+    pub fn request_car(&mut self, origin: Direction, mut car: QueuedCar) {
+        let Some(route) = route_between(origin, car.destination).filter(|_| self.layout.allows(origin, car.destination)) else {
+            println!("No route from {:?} to {:?}", origin, car.destination);
+            return;
+        };
+        self.movement_stats.get_mut(&(origin, route)).unwrap().demand += 1;
+        let car_id = car.id.get_or_insert_with(|| self.id_generator.get_next(origin, route)).clone();
+        let queue = self.spawn_queues.get_mut(&origin).unwrap();
+        if !queue.push(car) {
+            println!("Upstream queue heading {:?} is full, car dropped", origin);
+            if self.prebooked.remove(&car_id).is_some() {
+                self.v2i.uplink.send(self.now, &Message::Cancel { car_id, seq: 0 });
+            }
+            return;
+        }
+        self.release_spawn_queue(origin);
+
+        let waiting = self.spawn_queues[&origin].waiting.len();
+        if waiting > 0 {
+            println!("No free lane heading {:?}, {} car(s) queued upstream", origin, waiting);
+        }
+    }
+
+    fn release_spawn_queue(&mut self, origin: Direction) {
+        while let Some(queued) = self.spawn_queues[&origin].waiting.front().cloned() {
+            if !self.try_spawn(origin, queued) {
+                break;
+            }
+            self.spawn_queues.get_mut(&origin).unwrap().waiting.pop_front();
+        }
+    }
+
+    /// Ask the crossing manager for a slot on behalf of a car forecast to arrive
+    /// from upstream, before it is in any lane, as its request 0. It asks for the
+    /// lane a fresh car on its route would start in; the answer waits in `prebooked`.
+    pub fn prebook(&mut self, forecast: &ArrivalForecast) {
+        let Some(route) = route_between(forecast.heading, forecast.destination) else {
+            return;
+        };
+        let lane = self.layout.target_lane(route, Lane::Middle);
+        let distance_to_entry = self.entry_distance(forecast.heading, route);
+        let quickest = earliest_arrival(forecast.speed, distance_to_entry, forecast.speed).map_or(0.0, |p| p.duration());
+        let request = Message::Request {
+            car_id: forecast.car_id.clone(),
+            seq: 0,
+            at: forecast.at,
+            dir: forecast.heading,
+            lane,
+            route,
+            distance_to_entry,
+            speed: forecast.speed,
+            arrival: forecast.at + Duration::from_secs_f64(quickest),
+            length: CAR_HEIGHT_PX as f64,
+            width: CAR_WIDTH_PX as f64,
+            class: forecast.class,
+            requested_at: forecast.at,
+        };
+        self.v2i.uplink.send(self.now, &request);
+        self.prebooked.insert(forecast.car_id.clone(), (lane, None));
+    }
+
+    fn try_spawn(&mut self, direction: Direction, queued: QueuedCar) -> bool {
+        let Some(route) = route_between(direction, queued.destination) else {
+            return false;
+        };
+        let prebooked = queued.id.as_ref().and_then(|id| self.prebooked.get(id)).copied();
+        let lane = match prebooked {
+            Some((lane, _)) if self.lane_has_room(direction, lane) => lane,
+            _ => match self.pick_spawn_lane(direction, route) {
+                Some(lane) => lane,
+                None => return false,
+            },
+        };
+
+        let (x, y, spawn_speed) = self.layout.spawn_position(direction, lane);
+        let speed = queued.speed.unwrap_or(spawn_speed as f64);
+        let car_id = queued.id.clone().expect("Queued car should have an id");
+        let distance_to_entry = self.entry_distance(direction, route);
+
+        // Keep a slot booked upstream if it is for this lane and still in reach
+        let kept = prebooked.and_then(|(booked_lane, slot)| {
+            let (entry_time, crossing_speed) = slot?;
+            let time_left = entry_time.duration_since(self.now).unwrap_or(Duration::ZERO).as_secs_f64();
+            let profile = plan_arrival(speed, distance_to_entry, time_left, crossing_speed)?;
+            (booked_lane == self.layout.target_lane(route, lane)).then_some(SlotOffer { entry_time, crossing_speed, profile })
+        });
+        if prebooked.is_some() {
+            self.prebooked.remove(&car_id);
+            if kept.is_some() {
+                self.prebooks_kept += 1;
+            } else {
+                // Not confirmed yet, or out of reach: the car asks afresh on the road
+                self.prebooks_missed += 1;
+                self.v2i.uplink.send(self.now, &Message::Cancel { car_id: car_id.clone(), seq: 0 });
+            }
+        }
+
+        let slot = match (queued.driver, kept) {
+            (DriverType::Autonomous, Some(slot)) => slot,
+            // Human drivers book nothing and autonomous cars ask once on the road;
+            // the slot only seeds the car's speed
+            _ => SlotOffer { entry_time: self.now, crossing_speed: speed, profile: SpeedProfile::constant(speed) },
+        };
+
+        let texture = self.car_textures.get(&route.side()).expect("Missing texture for route");
+
+        let spawn = Spawn {
+            id: car_id.clone(),
+            x,
+            y,
+            width: CAR_WIDTH_PX,
+            height: CAR_HEIGHT_PX,
+            speed,
+            route,
+            direction,
+            lane,
+            slot,
+            now: self.now,
+        };
+        let mut car = Car::new(spawn, texture, self.layout);
+        car.time_requested = queued.requested_at;
+        car.onward = queued.onward;
+        car.driver = queued.driver;
+        car.class = queued.class;
+        if queued.driver == DriverType::Autonomous && kept.is_some() {
+            car.v2i = Client::handed_over();
+        } else {
+            car.held = queued.driver == DriverType::Autonomous;
+        }
+
+        self.cars_in.get_mut(&(direction, lane)).unwrap().push(car);
+
+        if queued.driver == DriverType::Human {
+            println!("Spawned human-driven car {} heading {:?} going {:?} in {:?} lane", car_id, direction, route, lane);
+            return true;
+        }
+
+        let booked = match kept {
+            Some(slot) => {
+                let datetime: DateTime<Local> = slot.entry_time.into();
+                format!(" | Entry time: {} at {:.0} px/s", datetime.format("%H:%M:%S%.3f"), slot.crossing_speed)
+            }
+            None => String::new(),
+        };
+        println!("Spawned {} {} heading {:?} going {:?} in {:?} lane{}", queued.class.name(), car_id, direction, route, lane, booked);
+        true
+    }
+
+    /// Lanes serving the route come first, then those one lane change away. A car
+    /// finds no lane further out than that and waits upstream instead.
+    fn pick_spawn_lane(&self, direction: Direction, route: Route) -> Option<Lane> {
+        let changes = |lane: Lane| lane.index().abs_diff(self.layout.target_lane(route, lane).index());
+        let mut lanes: Vec<Lane> = Lane::ALL.into_iter().filter(|&lane| changes(lane) <= 1).collect();
+        lanes.sort_by_key(|&lane| (changes(lane), self.cars_in[&(direction, lane)].len()));
+
+        lanes.into_iter().find(|&lane| self.lane_has_room(direction, lane))
+    }
+
+    /// Distance from a spawn point to the intersection for a car on `route`.
+    fn entry_distance(&self, heading: Direction, route: Route) -> f64 {
+        if self.layout.bypasses_zones(route) { ENTRY_DISTANCE_PX_RIGHT as f64 } else { self.layout.entry_distance(heading) as f64 }
+    }
+
+    fn lane_has_room(&self, direction: Direction, lane: Lane) -> bool {
+        car_spawn_check(&self.cars_in[&(direction, lane)], &self.layout.car_body(direction, lane))
+    }
+
+    /// Move cars that are in the wrong lane over by one lane when the gap allows.
+    /// Two cars side by side that each want the other's lane trade places, or both
+    /// would wait for ever at the end of the lane change area.
+    fn change_lanes(&mut self) {
+        let keys: Vec<(Direction, Lane)> = self.cars_in.keys().copied().collect();
+
+        for (direction, lane) in keys {
+            let mut i = 0;
+            while i < self.cars_in[&(direction, lane)].len() {
+                let car = &self.cars_in[&(direction, lane)][i];
+                let Some(next_lane) = car.next_lane().filter(|_| !car.collided) else {
+                    i += 1;
+                    continue;
+                };
+
+                if self.merge_gap(direction, car, next_lane, None) {
+                    let car = self.cars_in.get_mut(&(direction, lane)).unwrap().remove(i);
+                    self.merge(direction, car, next_lane);
+                } else if let Some(j) = self.swap_partner(direction, car, next_lane) {
+                    let car = self.cars_in.get_mut(&(direction, lane)).unwrap().remove(i);
+                    let partner = self.cars_in.get_mut(&(direction, next_lane)).unwrap().remove(j);
+                    println!("Cars {} and {} swap lanes", car.id, partner.id);
+                    self.merge(direction, car, next_lane);
+                    self.merge(direction, partner, lane);
+                } else {
+                    i += 1;
+                }
+            }
+        }
+    }
+
+    /// Whether `car` accepts the gap it would merge into in `lane`, leaving out
+    /// `partner`, the car it trades lanes with if any.
+    fn merge_gap(&self, direction: Direction, car: &Car, lane: Lane, partner: Option<&Car>) -> bool {
+        let target: Vec<&Car> = self.cars_in[&(direction, lane)]
+            .iter()
+            .filter(|other| partner.is_none_or(|p| p.id != other.id))
+            .collect();
+        let slot_index = target
+            .iter()
+            .position(|other| other.direction == car.direction && other.distance_to_entry() < car.distance_to_entry())
+            .unwrap_or(target.len());
+        let leader = slot_index.checked_sub(1).map(|j| target[j]);
+        let follower = target.get(slot_index).copied();
+
+        accepts_gap(&car.body_in_lane(lane), car.speed, leader, follower)
+    }
+
+    /// Car in `lane`, alongside `car`, that wants `car`'s lane in turn, when both
+    /// find room in the other's lane once the two of them have moved.
+    fn swap_partner(&self, direction: Direction, car: &Car, lane: Lane) -> Option<usize> {
+        let merged = car.body_in_lane(lane);
+        let queue = &self.cars_in[&(direction, lane)];
+        let j = queue.iter().position(|other| {
+            !other.collided && other.next_lane() == Some(car.lane) && other.body().overlaps(&merged)
+        })?;
+        let partner = &queue[j];
+
+        (self.merge_gap(direction, car, lane, Some(partner)) && self.merge_gap(direction, partner, car.lane, Some(car)))
+            .then_some(j)
+    }
+
+    /// Put `car` into `lane` behind the cars further ahead. A car reaching its
+    /// target lane asks for a new slot if its old one is out of reach.
+    fn merge(&mut self, direction: Direction, mut car: Car<'a>, lane: Lane) {
+        car.move_to_lane(lane);
+        println!("Car {} changed to {:?} lane", car.id, lane);
+
+        if car.driver == DriverType::Autonomous && car.lane == car.target_lane && !car.can_make_slot(self.now) {
+            let request = car.request_slot(self.now);
+            self.v2i.uplink.send(self.now, &request);
+        }
+
+        let queue = self.cars_in.get_mut(&(direction, lane)).unwrap();
+        let slot_index = queue
+            .iter()
+            .position(|other| other.direction == car.direction && other.distance_to_entry() < car.distance_to_entry())
+            .unwrap_or(queue.len());
+        queue.insert(slot_index, car);
     }
 
     fn check_cars_collision(&mut self) {
@@ -143,26 +579,422 @@ impl<'a> Intersection<'a> {
         }
     }
 
-    pub fn update(&mut self) {
-        let now = SystemTime::now();
-        let delta = now.duration_since(self.last_update).unwrap_or(BASE_DELTA_TIME);
-        self.last_update = now;
+    /// Block the zones ahead of every human-driven car, inferred from where it is
+    /// and how fast it goes, since it books nothing itself. One still in the wrong
+    /// lane cannot get there before it has merged, so it blocks nothing until then.
+    fn block_human_paths(&mut self) {
+        for car in self.cars_in.values().flatten() {
+            if car.driver != DriverType::Human || self.layout.bypasses_zones(car.route) || car.lane != car.target_lane {
+                continue;
+            }
+            let path = self.layout.timed_zone_path(car.origin, car.target_lane, car.route);
+            let distance = if car.in_intersection { -car.travelled_inside } else { car.remaining_to_entry() };
+            let slowest = (car.speed * HUMAN_SPEED_FACTOR_MIN).max(HUMAN_PROJECTED_MIN_SPEED);
+            self.crossing_manager.block_observed(&car.id, &path, distance, SPEED_PX_PER_SEC, slowest);
+        }
+    }
+
+    /// Answer what reached the crossing manager over the radio, knowing of each
+    /// car only what it said there. New requests are served strongest claim
+    /// first. Emergency vehicles, and buses, freight trucks or starved cars asking
+    /// for the first time or left waiting, book ahead of the cars with a weaker
+    /// claim; other cars take the first free slot. A car whose path crosses that
+    /// of a human driver waiting to go is turned down while it can still stop,
+    /// unless it is an emergency vehicle, and so is a car queued behind one still
+    /// waiting for a slot. Requests made for cars still upstream take the first
+    /// free slot from their arrival. A car asking to go ahead, or asking for a
+    /// slot from that close, is let go if it can still make its slot, and from
+    /// then on its slot is not touched.
+    fn serve_v2i(&mut self) {
+        let now = self.now;
+        let waiting: Vec<Vec<ZoneIndex>> = self
+            .waiting_humans()
+            .map(|car| self.layout.zone_path(car.origin, car.target_lane, car.route))
+            .collect();
+        let claim = |message: &Message| match message {
+            Message::Request { class, requested_at, .. } => class.priority(now.duration_since(*requested_at).unwrap_or_default()),
+            _ => f64::INFINITY,
+        };
+        let mut messages = self.v2i.uplink.receive(now);
+        messages.sort_by(|a, b| claim(b).total_cmp(&claim(a)));
+
+        for message in messages {
+            match message {
+                Message::Request { ref car_id, seq, at, dir, lane, route, distance_to_entry, speed, length, class, requested_at, .. } => {
+                    if !self.v2i.is_new(car_id, seq, requested_at) {
+                        continue;
+                    }
+                    let waited = now.duration_since(requested_at).unwrap_or_default();
+                    let ahead = class == VehicleClass::Emergency
+                        || (class.may_preempt(waited)
+                            && self.v2i.sessions.get(car_id).is_none_or(|session| session.state == SessionState::Rejected));
+                    self.crossing_manager.cancel(car_id);
+                    self.v2i.open(now, &message);
+
+                    let path = self.layout.zone_path(dir, lane, route);
+                    let gives_way = class != VehicleClass::Emergency
+                        && self.v2i.sessions[car_id].can_stop(now)
+                        && waiting.iter().any(|other| other.iter().any(|zone| path.contains(zone)));
+                    // Planned from where the car said it would be, or from when the
+                    // answer reaches it if later: it waits for it short of the entry line
+                    let approach = Approach { dir, lane, route, distance_to_entry, speed, length };
+                    let from = at.max(now + self.v2i.downlink.latency);
+                    let slot = if seq == 0 {
+                        self.crossing_manager.reserve_path_from(car_id, approach, from)
+                    } else if gives_way || self.queued_behind_unbooked(car_id, dir, lane, distance_to_entry) {
+                        None
+                    } else if ahead {
+                        self.preempt(car_id, approach, from, class, requested_at)
+                    } else {
+                        self.crossing_manager.reserve_path_from(car_id, approach, from)
+                    };
+                    // A car asking from where it would ask to go ahead is let go at once
+                    let committed = distance_to_entry <= V2I_GO_AHEAD_DISTANCE_PX;
+                    self.answer(car_id, slot.map(|slot| (slot.entry_time, slot.crossing_speed)), committed, false);
+                }
+                Message::Update { car_id, seq, at, distance_to_entry, speed } => {
+                    let Some(session) = self.v2i.sessions.get_mut(&car_id).filter(|session| session.seq == seq) else {
+                        continue;
+                    };
+                    session.heard_at = now;
+                    session.at = at;
+                    session.approach.distance_to_entry = distance_to_entry;
+                    session.approach.speed = speed;
+                    let slot = match (session.state, session.slot) {
+                        (SessionState::Booked | SessionState::Committed, Some((entry_time, crossing_speed))) => {
+                            let time_left = entry_time.duration_since(at).unwrap_or_default().as_secs_f64();
+                            plan_arrival(speed, distance_to_entry, time_left, crossing_speed).map(|_| (entry_time, crossing_speed))
+                        }
+                        _ => None,
+                    };
+                    if slot.is_none() {
+                        self.crossing_manager.cancel(&car_id);
+                    }
+                    self.answer(&car_id, slot, true, false);
+                }
+                Message::Cancel { car_id, seq } => {
+                    if self.v2i.close(&car_id, seq) {
+                        self.crossing_manager.cancel(&car_id);
+                    }
+                }
+                Message::Confirm { .. } | Message::Reject { .. } => {}
+            }
+        }
+        let crossing_manager = &self.crossing_manager;
+        self.v2i.expire(now, |car_id| crossing_manager.holds_zones(car_id));
+    }
+
+    /// Whether an autonomous car that said it was nearer the entry line than
+    /// `distance`, in the same lane as `car_id`, is still without a slot. Cars on
+    /// the same approach keep their order, so the one behind waits rather than
+    /// take the zones the one ahead needs.
+    fn queued_behind_unbooked(&self, car_id: &str, dir: Direction, lane: Lane, distance: f64) -> bool {
+        self.v2i.sessions.iter().any(|(id, session)| {
+            let ahead = session.approach;
+            id != car_id
+                && session.state == SessionState::Rejected
+                && (ahead.dir, ahead.lane) == (dir, lane)
+                && ahead.distance_to_entry < distance
+                && !self.layout.bypasses_zones(ahead.route)
+        })
+    }
+
+    /// Move or withdraw the slots of autonomous cars that can still stop, going
+    /// by where they said they were and the plan they were given, when a slot
+    /// clashes with a blocked zone or its path crosses that of a human driver
+    /// waiting to go, and tell the cars. Emergency vehicles give way to nobody and
+    /// book again ahead of everyone else.
+    fn review_bookings(&mut self) {
+        let now = self.now;
+        let waiting: Vec<Vec<ZoneIndex>> = self
+            .waiting_humans()
+            .map(|car| self.layout.zone_path(car.origin, car.target_lane, car.route))
+            .collect();
+        let mut changes = Vec::new();
+
+        for (car_id, session) in &self.v2i.sessions {
+            if session.state != SessionState::Booked || !session.can_stop(now) || self.layout.bypasses_zones(session.approach.route) {
+                continue;
+            }
+            let (approach, from) = session.whereabouts(now);
+            let emergency = session.class == VehicleClass::Emergency;
+            // Cars still upstream are not held up by who waits now
+            let on_road = session.at <= now;
+            let path = self.layout.zone_path(approach.dir, approach.lane, approach.route);
+            if !emergency && on_road && waiting.iter().any(|other| other.iter().any(|zone| path.contains(zone))) {
+                println!("Car {} gives way to a human driver", car_id);
+                changes.push((session.priority(now), car_id.clone(), None, from, session.class, session.requested_at));
+            } else if self.crossing_manager.has_conflict(car_id) {
+                changes.push((session.priority(now), car_id.clone(), Some(approach), from, session.class, session.requested_at));
+            }
+        }
+
+        changes.sort_by(|a, b| b.0.total_cmp(&a.0));
+        for (_, id, approach, from, class, requested_at) in changes {
+            self.crossing_manager.cancel(&id);
+            let slot = approach.and_then(|approach| match class {
+                VehicleClass::Emergency => self.preempt(&id, approach, from, class, requested_at),
+                _ => self.crossing_manager.reserve_path_from(&id, approach, from),
+            });
+            self.answer(&id, slot.map(|slot| (slot.entry_time, slot.crossing_speed)), false, false);
+        }
+    }
+
+    /// Tell a car what the crossing manager holds for it now, if anything, and
+    /// whether it lost its last slot to a car with a stronger claim.
+    fn answer(&mut self, car_id: &str, slot: Option<(SystemTime, f64)>, committed: bool, displaced: bool) {
+        let windows = self.crossing_manager.windows_of(car_id);
+        self.v2i.answer(self.now, car_id, slot, windows, committed, displaced);
+    }
+
+    /// Autonomous cars take in the answers that reached them and say what they
+    /// have to say in turn, see `Car::talk`. Answers for cars still upstream go
+    /// to `prebooked`.
+    fn drive_v2i(&mut self) {
+        let now = self.now;
+        let mut inbox: HashMap<String, Vec<Message>> = HashMap::new();
+        for message in self.v2i.downlink.receive(now) {
+            inbox.entry(message.car_id().to_string()).or_default().push(message);
+        }
+        let mut outbox = Vec::new();
+
+        for car in self.cars_in.values_mut().flatten() {
+            if car.driver == DriverType::Human {
+                continue;
+            }
+            for message in inbox.remove(&car.id).unwrap_or_default() {
+                car.receive(&message, now);
+            }
+            if car.in_intersection || car.collided {
+                continue;
+            }
+            if car.v2i.timed_out(now) {
+                self.v2i.timeouts += 1;
+            }
+            outbox.extend(car.talk(now));
+        }
+        for message in outbox {
+            self.v2i.uplink.send(now, &message);
+        }
+
+        for (car_id, messages) in inbox {
+            for message in messages {
+                match message {
+                    Message::Confirm { seq: 0, entry_time, crossing_speed, .. } => {
+                        if let Some((_, slot)) = self.prebooked.get_mut(&car_id) {
+                            *slot = Some((entry_time, crossing_speed));
+                        }
+                    }
+                    Message::Reject { seq: 0, .. } => {
+                        self.prebooked.remove(&car_id);
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    /// Book a car of `class` that asked to enter at `requested_at`, and is at
+    /// `approach` at time `from`, through ahead of every autonomous car with a
+    /// weaker claim whose slot may still be moved and that can still stop, by
+    /// what the manager last heard from it. Emergency vehicles also go ahead of
+    /// every human driver still waiting to go. Cars on the same approach keep
+    /// their order. The cars pushed aside are told their new slot, or to wait
+    /// short of the entry line if nothing is in reach.
+    fn preempt(
+        &mut self, car_id: &str, approach: Approach, from: SystemTime, class: VehicleClass, requested_at: SystemTime,
+    ) -> Option<SlotOffer> {
+        let now = self.now;
+        let priority = class.priority(now.duration_since(requested_at).unwrap_or_default());
+        let mut movable: Vec<String> = self
+            .v2i
+            .sessions
+            .iter()
+            .filter(|(id, session)| id.as_str() != car_id && session.state == SessionState::Booked)
+            .filter(|(_, session)| session.priority(now) < priority && session.approach.dir != approach.dir)
+            .filter(|(_, session)| session.can_stop(now))
+            .map(|(id, _)| id.clone())
+            .collect();
+        if class == VehicleClass::Emergency {
+            movable.extend(self.waiting_humans().map(|car| car.id.clone()));
+        }
+        let (slot, displaced) = self.crossing_manager.preempt_path(car_id, approach, from, |id| movable.iter().any(|m| m == id))?;
+
+        for id in displaced {
+            // Waiting human drivers get their zones blocked again on the next tick
+            let Some(session) = self.v2i.sessions.get(&id) else {
+                continue;
+            };
+            let (moved, moved_from) = session.whereabouts(now);
+            println!("Car {} makes way for {} {}", id, class.name(), car_id);
+            // Windows already under way were kept through the preemption
+            self.crossing_manager.cancel(&id);
+            let moved_slot = self.crossing_manager.reserve_path_from(&id, moved, moved_from);
+            self.answer(&id, moved_slot.map(|slot| (slot.entry_time, slot.crossing_speed)), false, true);
+        }
+        Some(slot)
+    }
+
+    /// Human drivers settled at the head of their lane and near the entry line,
+    /// still deciding whether to go.
+    fn waiting_humans(&self) -> impl Iterator<Item = &Car<'_>> {
+        self.cars_in
+            .values()
+            .filter_map(|queue| queue.iter().find(|car| !car.in_intersection))
+            .filter(|car| car.driver == DriverType::Human && !car.cleared && car.lane == car.target_lane)
+            .filter(|car| car.remaining_to_entry() <= HUMAN_DECISION_DISTANCE_PX)
+    }
+
+    /// Nearest car ahead of each car past the entry line in the same lane and
+    /// heading the same way, from any approach. A car's queue only holds the cars
+    /// from its own, but past the box cars that turned in from others share its lane.
+    fn lane_leaders(&self) -> HashMap<String, Leader> {
+        let cars: Vec<&Car> = self.cars_in.values().flatten().collect();
+        cars.iter()
+            .filter(|car| car.in_intersection)
+            .filter_map(|car| {
+                let front = car.body().along(car.direction).1;
+                cars.iter()
+                    .filter(|other| other.id != car.id && other.direction == car.direction)
+                    .filter(|other| other.body().along(car.direction).1 > front)
+                    .filter_map(|other| {
+                        let speed = if other.collided { 0.0 } else { other.speed };
+                        car.gap_to(other).map(|gap| Leader { gap, speed })
+                    })
+                    .min_by(|a, b| a.gap.total_cmp(&b.gap))
+                    .map(|leader| (car.id.clone(), leader))
+            })
+            .collect()
+    }
+
+    /// Human drivers near the entry line go once their path has looked clear for
+    /// a reaction time.
+    fn give_way(&mut self) {
+        let now = self.now;
+        let cars: Vec<&Car> = self.cars_in.values().flatten().collect();
+        let clear: Vec<(String, bool)> = self
+            .waiting_humans()
+            .map(|car| (car.id.clone(), self.path_is_clear(car, &cars)))
+            .collect();
+
+        for car in self.cars_in.values_mut().flatten() {
+            let Some(&(_, is_clear)) = clear.iter().find(|(id, _)| *id == car.id) else {
+                continue;
+            };
+            if !is_clear {
+                car.clear_since = None;
+                continue;
+            }
+            let since = *car.clear_since.get_or_insert(now);
+            car.cleared = since + HUMAN_REACTION_TIME <= now;
+        }
+    }
+
+    /// What a driver at the entry line can see: nobody in the box on a path that
+    /// crosses theirs, nobody going for such a path within the critical gap, and
+    /// no pedestrian on or about to step onto a crosswalk who has yet to pass them.
+    fn path_is_clear(&self, car: &Car, cars: &[&Car]) -> bool {
+        let path = self.layout.zone_path(car.origin, car.target_lane, car.route);
+        let crosses = |other: &Car| {
+            self.layout.zone_path(other.origin, other.target_lane, other.route).iter().any(|zone| path.contains(zone))
+        };
+        let zone_rects: Vec<Rect> = path.iter().map(|&zone| self.layout.zone_rect(zone)).collect();
+        let box_rect = self.layout.box_rect();
+        let lane_heads: Vec<&str> = self
+            .cars_in
+            .values()
+            .filter_map(|queue| queue.iter().find(|other| !other.in_intersection))
+            .map(|other| other.id.as_str())
+            .collect();
+
+        let cars_clear = cars.iter().filter(|other| other.id != car.id).all(|other| {
+            if zone_rects.iter().any(|&rect| other.overlaps(rect)) {
+                return false;
+            }
+            if !crosses(other) {
+                return true;
+            }
+            if other.in_intersection {
+                return !other.overlaps(box_rect);
+            }
+            // Nobody goes ahead of an emergency vehicle holding a slot with a free run to the entry line
+            let free_run = other.lane == other.target_lane && lane_heads.contains(&other.id.as_str());
+            if other.class == VehicleClass::Emergency && !other.held && other.origin != car.origin && free_run {
+                return false;
+            }
+            // An autonomous car past the point where it could still wait is committed to its slot
+            let committed = other.driver == DriverType::Autonomous
+                && !other.held
+                && free_run
+                && other.remaining_to_entry() < AV_HOLD_DISTANCE_PX;
+            let arriving = other.speed > STOPPED_SPEED_PX_PER_SEC && other.remaining_to_entry() / other.speed < HUMAN_CRITICAL_GAP;
+            // Of two drivers waiting on crossing paths, the one who saw a gap first goes first
+            let first = other.clear_since.is_some_and(|since| (since, &other.id) < (car.clear_since.unwrap_or(self.now), &car.id));
+            !(other.cleared || committed || arriving || first)
+        });
+
+        let soon = self.now + Duration::from_secs_f64(HUMAN_CRITICAL_GAP);
+        let walkers_clear = self
+            .pedestrians
+            .iter()
+            .all(|pedestrian| pedestrian.start_at > soon || !pedestrian.cells_ahead(self.layout).iter().any(|cell| path.contains(cell)));
+
+        cars_clear && walkers_clear
+    }
+
+    /// Pedestrians touched by a car count once each, however long the contact lasts.
+    fn check_pedestrian_conflicts(&mut self) {
+        for pedestrian in self.pedestrians.iter_mut().filter(|p| !p.conflict) {
+            let bb = pedestrian.bounding_box();
+            if let Some(car) = self.cars_in.values().flatten().find(|car| car.overlaps(bb)) {
+                println!("Car {} ran into pedestrian {}", car.id, pedestrian.id);
+                pedestrian.conflict = true;
+                self.pedestrian_conflicts += 1;
+            }
+        }
+    }
+
+    /// Advance the simulation clock by `delta` and move everything along with it.
+    pub fn update(&mut self, delta: Duration) {
+        self.now += delta;
+        let now = self.now;
 
         self.check_cars_collision();
-        self.crossing_manager.update();
+        self.check_pedestrian_conflicts();
+        self.crossing_manager.update(now);
+        self.block_human_paths();
+        self.serve_v2i();
+        self.review_bookings();
+        self.drive_v2i();
+        self.give_way();
+        self.change_lanes();
+        for origin in Direction::ALL {
+            self.release_spawn_queue(origin);
+            self.spawn_queues.get_mut(&origin).unwrap().record(delta);
+        }
+        self.record_movement_queues(delta);
+        let lane_leaders = self.lane_leaders();
         for queue in self.cars_in.values_mut() {
             let mut i = 0;
 
             while i < queue.len() {
-                // Check if there's a front car and if current car should brake
-                let should_brake = if i > 0 {
+                // Gap and speed of the front car drive the follower's acceleration
+                let front_leader = if i > 0 {
                     // Get immutable reference to front car first
                     let front_car = &queue[i - 1];
                     let current_car = &queue[i];
-                    !current_car.collided && current_car.is_too_close(front_car)
+                    current_car.gap_to(front_car).map(|gap| Leader {
+                        gap,
+                        speed: if front_car.collided { 0.0 } else { front_car.speed },
+                    })
                 } else {
-                    false
+                    None
                 };
+                let lane_leader = lane_leaders.get(&queue[i].id).copied();
+                let leader = [front_leader, lane_leader, queue[i].lane_change_stop(), queue[i].entry_stop(now)]
+                    .into_iter()
+                    .flatten()
+                    .min_by(|a, b| a.gap.total_cmp(&b.gap));
 
                 // Now get mutable reference to current car
                 let car = &mut queue[i];
@@ -172,29 +1004,123 @@ impl<'a> Intersection<'a> {
                     continue;
                 }
 
-                if !car.brake && should_brake { self.near_miss += 1}
-                car.brake = should_brake;
-                car.update(delta);
+                let (was_braking, was_inside) = (car.brake, car.in_intersection);
+                car.update(now, delta, leader);
+                if !was_braking && car.brake { self.near_miss += 1 }
+
+                if !was_inside && car.in_intersection && car.driver == DriverType::Autonomous && let Some(&destination) = car.onward.first() {
+                    self.forecasts.push(forecast_exit(car, destination, now));
+                }
 
                 if car.exited {
-                    let exited_car = queue.remove(i);
+                    let mut exited_car = queue.remove(i);
+                    if exited_car.driver == DriverType::Human {
+                        self.crossing_manager.cancel(&exited_car.id);
+                    } else {
+                        let cancel = Message::Cancel { car_id: exited_car.id.clone(), seq: exited_car.v2i.close() };
+                        self.v2i.uplink.send(now, &cancel);
+                    }
+                    if let Some((&destination, onward)) = exited_car.onward.split_first() {
+                        self.outbound.push((exited_car.direction, QueuedCar {
+                            destination,
+                            requested_at: now,
+                            onward: onward.to_vec(),
+                            id: Some(exited_car.id.clone()),
+                            speed: Some(exited_car.speed),
+                            driver: exited_car.driver,
+                            class: exited_car.class,
+                        }));
+                    }
                     self.cars_out.push(exited_car);
                 } else {
                     i += 1;
                 }
             }
         }
+
+        for pedestrian in self.pedestrians.iter_mut() {
+            pedestrian.update(now, delta);
+        }
+        let (across, crossing): (Vec<Pedestrian>, Vec<Pedestrian>) =
+            std::mem::take(&mut self.pedestrians).into_iter().partition(|p| p.finished_at.is_some());
+        self.pedestrians = crossing;
+        self.pedestrians_out.extend(across);
     }
 
-    pub fn draw(&self, canvas: &mut sdl2::render::Canvas<sdl2::video::Window>) {
+    pub fn draw<T: RenderTarget>(&self, canvas: &mut Canvas<T>) {
         for queue in self.cars_in.values() {
             for car in queue {
                 car.draw(canvas);
             }
         }
+        for pedestrian in &self.pedestrians {
+            pedestrian.draw(canvas);
+        }
         let _ = self.crossing_manager.draw(canvas);
     }
 
+    /// Count cars queued per movement: waiting upstream, or stopped short of the intersection.
+    fn record_movement_queues(&mut self, delta: Duration) {
+        let mut lengths: HashMap<Movement, usize> = HashMap::new();
+
+        for (&origin, queue) in &self.spawn_queues {
+            for queued in &queue.waiting {
+                if let Some(route) = route_between(origin, queued.destination) {
+                    *lengths.entry((origin, route)).or_default() += 1;
+                }
+            }
+        }
+        for car in self.cars_in.values().flatten() {
+            if !car.in_intersection && car.speed < STOPPED_SPEED_PX_PER_SEC {
+                *lengths.entry((car.origin, car.route)).or_default() += 1;
+            }
+        }
+
+        for (movement, stats) in self.movement_stats.iter_mut() {
+            stats.record_queue(lengths.get(movement).copied().unwrap_or(0), delta);
+        }
+    }
+
+    /// Longest wait so far per movement of the cars yet to enter the intersection,
+    /// queued upstream or still on their approach.
+    fn current_waits(&self) -> HashMap<Movement, f64> {
+        let now = self.now;
+        let mut waits: HashMap<Movement, f64> = HashMap::new();
+        let mut record = |movement: Movement, since: SystemTime| {
+            let wait = now.duration_since(since).unwrap_or_default().as_secs_f64();
+            let longest = waits.entry(movement).or_default();
+            *longest = longest.max(wait);
+        };
+
+        for (&origin, queue) in &self.spawn_queues {
+            for queued in &queue.waiting {
+                if let Some(route) = route_between(origin, queued.destination) {
+                    record((origin, route), queued.requested_at);
+                }
+            }
+        }
+        for car in self.cars_in.values().flatten().filter(|car| !car.in_intersection) {
+            record((car.origin, car.route), car.time_requested);
+        }
+
+        waits
+    }
+
+    /// Simulated time since the intersection opened.
+    pub fn elapsed(&self) -> Duration {
+        self.now.duration_since(self.started_at).unwrap_or_default()
+    }
+
+    /// Simulated seconds from the start of the run to `time`.
+    pub fn sim_seconds(&self, time: SystemTime) -> f64 {
+        time.duration_since(self.started_at).unwrap_or_default().as_secs_f64()
+    }
+
+    pub fn get_movement_report(&self) -> String {
+        let elapsed = self.elapsed().as_secs_f64();
+        movement_report(&self.cars_out, &self.current_waits(), &self.movement_stats, elapsed, self.layout)
+    }
+
     pub fn get_statistics(&self) -> String {
         let cars = &self.cars_out;
         let total = cars.len();
@@ -205,11 +1131,40 @@ impl<'a> Intersection<'a> {
 
         let (min_speed, max_speed, avg_speed) = calculate_speed_statistics(cars);
         let (min_duration, max_duration, avg_duration) = calculate_duration_statistics(cars);
+        let (_, max_wait, avg_wait) = calculate_wait_statistics(cars);
+        let samples: Vec<DelaySample> = cars.iter().filter_map(DelaySample::from_car).collect();
+        let delays: Vec<f64> = samples.iter().map(|s| s.control_delay).collect();
+        let avg_stops = samples.iter().map(|s| s.stops).sum::<usize>() as f32 / samples.len().max(1) as f32;
+
+        let queue_lines: String = self
+            .layout
+            .approaches()
+            .map(|dir| {
+                let queue = &self.spawn_queues[&dir];
+                format!(
+                    "Queue {:?}: now {}, max {}, avg {:.2}\n",
+                    dir,
+                    queue.waiting.len(),
+                    queue.max_length,
+                    queue.average_length()
+                )
+            })
+            .collect();
+        let dropped: usize = self.spawn_queues.values().map(|queue| queue.dropped).sum();
+        let emergency_delays: Vec<f64> = samples.iter().filter(|s| s.class == VehicleClass::Emergency).map(|s| s.control_delay).collect();
+        let added_delays: Vec<f64> = cars
+            .iter()
+            .filter_map(|car| Some(car.actual_entry_time?.duration_since(car.displaced_from?).unwrap_or_default().as_secs_f64()))
+            .collect();
+        let pedestrian_waits: Vec<f64> = self.pedestrians_out.iter().map(|p| p.waited().as_secs_f64()).collect();
+        let max_pedestrian_wait = pedestrian_waits.iter().copied().fold(0.0, f64::max);
+        let avg_pedestrian_wait = pedestrian_waits.iter().sum::<f64>() / pedestrian_waits.len().max(1) as f64;
 
         format!(
             "Intersection Statistics\n\
             -----------------------------\n\
             Vehicles Crossed: {}\n\
+            Human-driven: {}\n\
             Collisions: {}\n\
             Near Misses: {}\n\
             \n\
@@ -219,8 +1174,27 @@ impl<'a> Intersection<'a> {
             \n\
             Max Time in Intersection: {} s\n\
             Min Time in Intersection: {} s\n\
-            Avg Time in Intersection: {} s",
+            Avg Time in Intersection: {} s\n\
+            \n\
+            Control Delay p50/p90/p99: {:.2} / {:.2} / {:.2} s\n\
+            Avg Stops: {}\n\
+            Max Upstream Wait: {} s\n\
+            Avg Upstream Wait: {} s\n\
+            {}\
+            Dropped Demand: {}\n\
+            Pre-booked Slots Kept/Missed: {} / {}\n\
+            V2I Messages Sent/Lost: {} / {}\n\
+            V2I Timeouts: {}\n\
+            \n\
+            Emergency Vehicles Crossed: {}\n\
+            Emergency Delay avg/max: {:.2} / {:.2} s\n\
+            Cars Displaced: {}, added delay avg {:.2} s\n\
+            \n\
+            Pedestrians Crossed: {}\n\
+            Pedestrian Wait avg/max: {:.2} / {:.2} s\n\
+            Pedestrian Conflicts: {}",
             total,
+            cars.iter().filter(|car| car.driver == DriverType::Human).count(),
             self.collision_count,
             self.near_miss,
             max_speed,
@@ -228,7 +1202,97 @@ impl<'a> Intersection<'a> {
             avg_speed,
             max_duration,
             min_duration,
-            avg_duration
+            avg_duration,
+            percentile(&delays, 50.0),
+            percentile(&delays, 90.0),
+            percentile(&delays, 99.0),
+            round_two(avg_stops),
+            max_wait,
+            avg_wait,
+            queue_lines,
+            dropped,
+            self.prebooks_kept,
+            self.prebooks_missed,
+            self.v2i.uplink.sent + self.v2i.downlink.sent,
+            self.v2i.uplink.lost + self.v2i.downlink.lost,
+            self.v2i.timeouts,
+            emergency_delays.len(),
+            emergency_delays.iter().sum::<f64>() / emergency_delays.len().max(1) as f64,
+            emergency_delays.iter().copied().fold(0.0, f64::max),
+            added_delays.len(),
+            added_delays.iter().sum::<f64>() / added_delays.len().max(1) as f64,
+            self.pedestrians_out.len(),
+            avg_pedestrian_wait,
+            max_pedestrian_wait,
+            self.pedestrian_conflicts
         )
     }
 }
+
+/// Forecast for a car that just entered: it keeps its crossing speed until its
+/// tail leaves the tile, and arrives at the next intersection's spawn point then.
+fn forecast_exit(car: &Car, destination: Direction, now: SystemTime) -> ArrivalForecast {
+    let remaining = (car.dist + TILE_EXIT_MARGIN_PX - car.layout.entry_distance(car.origin)) as f64;
+    ArrivalForecast {
+        car_id: car.id.clone(),
+        heading: destination_of(car.origin, car.route),
+        destination,
+        at: now + Duration::from_secs_f64(remaining.max(0.0) / car.crossing_speed),
+        speed: car.crossing_speed,
+        class: car.class,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sdl2::pixels::PixelFormatEnum;
+    use sdl2::surface::Surface;
+
+    /// Stopped car on `route` in `lane`, level with the end of the lane change area.
+    fn stopped_car<'a>(intersection: &Intersection<'a>, id: &str, lane: Lane, route: Route) -> Car<'a> {
+        let (x, y, _) = intersection.layout.spawn_position(Direction::North, lane);
+        let spawn = Spawn {
+            id: id.to_string(),
+            x,
+            y,
+            width: CAR_WIDTH_PX,
+            height: CAR_HEIGHT_PX,
+            speed: 0.0,
+            route,
+            direction: Direction::North,
+            lane,
+            slot: SlotOffer { entry_time: intersection.now, crossing_speed: 0.0, profile: SpeedProfile::constant(0.0) },
+            now: intersection.now,
+        };
+        let mut car = Car::new(spawn, intersection.car_textures[&route], intersection.layout);
+        let distance = LANE_CHANGE_END_PX - car.distance_to_entry();
+        apply_movement_for_direction(&mut car.x, &mut car.y, distance, Direction::North);
+        car
+    }
+
+    #[test]
+    fn wrong_lane_cars_side_by_side_trade_lanes_and_cross() {
+        let layout = Layout::standard();
+        let canvas = Surface::new(1, 1, PixelFormatEnum::RGBA8888).unwrap().into_canvas().unwrap();
+        let creator = canvas.texture_creator();
+        let texture = creator.create_texture_target(None, 1, 1).unwrap();
+        let textures = [Route::Left, Route::Right, Route::Straight].into_iter().map(|route| (route, &texture)).collect();
+        let mut intersection = Intersection::new(&layout, textures, OdMatrix::uniform(), None, SystemTime::now());
+
+        // Each wants the lane the other is in
+        let right = stopped_car(&intersection, "NRT-0001", Lane::Median, Route::Right);
+        let left = stopped_car(&intersection, "NLT-0002", Lane::Middle, Route::Left);
+        assert!(right.body_in_lane(Lane::Middle).overlaps(&left.body()));
+        intersection.cars_in.get_mut(&(Direction::North, Lane::Median)).unwrap().push(right);
+        intersection.cars_in.get_mut(&(Direction::North, Lane::Middle)).unwrap().push(left);
+
+        for _ in 0..(60_000 / BASE_DELTA_TIME.as_millis()) {
+            intersection.update(BASE_DELTA_TIME);
+        }
+
+        let crossed: Vec<&str> = intersection.cars_out.iter().map(|car| car.id.as_str()).collect();
+        assert!(crossed.contains(&"NRT-0001") && crossed.contains(&"NLT-0002"), "crossed: {:?}", crossed);
+        assert_eq!(intersection.collision_count, 0);
+    }
+}
@@ -1,16 +1,46 @@
+mod camera;
 mod car;
 mod cars_id;
+mod clock;
 mod consts;
+mod inspector;
 mod intersection;
+mod map;
+mod metrics;
+mod network;
+mod pedestrian;
+mod prometheus;
+mod spawn_queue;
+mod timeseries;
 mod crossing_manager;
+mod demand;
+mod gantt;
+mod hud;
+mod speed_profile;
 mod utils;
+mod v2i;
 
+use camera::Camera;
+use car::VehicleClass;
+use clock::SimClock;
+use demand::{FixedArrivals, OdMatrix};
+use gantt::GanttView;
+use hud::Hud;
+use prometheus::MetricsServer;
+use timeseries::TimeSeriesWriter;
 use intersection::{Intersection, Direction, Route};
-use sdl2::image::{InitFlag, LoadTexture};
-use sdl2::event::Event;
+use map::RoadMap;
+use network::Network;
+use sdl2::image::{InitFlag, LoadTexture, SaveSurface};
+use sdl2::event::{Event, WindowEvent};
 use sdl2::keyboard::Keycode;
-use sdl2::render::Texture;
+use sdl2::mouse::MouseButton;
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::render::{Texture, TextureCreator};
+use sdl2::surface::Surface;
+use sdl2::ttf::Sdl2TtfContext;
 use std::collections::HashMap;
+use std::time::Duration;
 
 use crate::consts::*;
 use crate::utils::present_main_canvas;
@@ -18,9 +48,15 @@ use crate::utils::present_main_canvas;
 
 pub fn main() {
     let sdl_context = sdl2::init().unwrap();
-    let video_subsystem = sdl_context.video().unwrap();
-
     let _img_ctx = sdl2::image::init(InitFlag::PNG);
+    let ttf_context = sdl2::ttf::init().unwrap();
+
+    if let Some(dir) = arg_value("--render") {
+        render_frames(&dir, &ttf_context);
+        return;
+    }
+
+    let video_subsystem = sdl_context.video().unwrap();
 
     let window = video_subsystem.window("smart-road", SIMULATION_WINDOW_WIDTH, SIMULATION_WINDOW_HEIGHT)
         .position_centered()
@@ -29,53 +65,128 @@ pub fn main() {
 
     let mut canvas = window.into_canvas().build().unwrap();
     
-    // Load the background texture
     let texture_creator = canvas.texture_creator();
-    let bg_texture = texture_creator.load_texture("assets/bg.png").unwrap();
-    let car_right_texture = texture_creator.load_texture("assets/car_r.png").unwrap();
-    let car_left_texture = texture_creator.load_texture("assets/car_l.png").unwrap();
-    let car_straight_texture = texture_creator.load_texture("assets/car_s.png").unwrap();
+    let textures = Textures::load(&texture_creator);
+    let mut time_series = time_series_from_args();
+    let mut arrivals = arrivals_from_args();
+    let mut pedestrian_arrivals = pedestrian_arrivals_from_args();
+    let mut emergency_arrivals = emergency_arrivals_from_args();
+
+    let metrics_server = arg_value("--metrics-port").map(|port| {
+        let port = port.parse().expect("--metrics-port takes a port number");
+        MetricsServer::start(port).unwrap_or_else(|e| panic!("Failed to serve metrics on port {}: {}", port, e))
+    });
 
-    let car_textures_by_route: HashMap<Route, &Texture> = HashMap::from([
-        (Route::Right, &car_right_texture),
-        (Route::Left, &car_left_texture),
-        (Route::Straight, &car_straight_texture),
-    ]);
+    let mut hud = Hud::new(ttf_context.load_font(FONT_PATH, HUD_FONT_SIZE).unwrap());
+    let main_window_id = canvas.window().id();
+    let mut gantt: Option<GanttView> = None;
 
     let mut event_pump = sdl_context.event_pump().unwrap();
-    let mut intersection = Intersection::new(car_textures_by_route);
+    let road_map = road_map_from_args();
+    let mut network = network_from_args(&road_map, textures.by_route());
+    let (network_width, network_height) = network.size();
+    let mut camera = Camera::fit(network_width, network_height);
+    let mut drag_from: Option<(i32, i32)> = None;
 
     use std::time::Instant;
 
     let target_frame_duration = BASE_DELTA_TIME;
+    let mut clock = SimClock::new();
+    let mut last_frame = Instant::now();
 
     'running: loop {
         let frame_start = Instant::now();
+        let frame_time = frame_start - last_frame;
+        last_frame = frame_start;
         let events: Vec<_> = event_pump.poll_iter().collect();
 
         for event in events {
             match event {
                 Event::Quit { .. } => break 'running,
 
+                Event::Window { window_id, win_event: WindowEvent::Close, .. } => {
+                    if window_id == main_window_id {
+                        break 'running;
+                    }
+                    if gantt.as_ref().is_some_and(|view| view.window_id() == window_id) {
+                        gantt = None;
+                    }
+                }
+
+                Event::MouseButtonDown { window_id, mouse_btn: MouseButton::Left, x, y, .. }
+                    if window_id == main_window_id =>
+                {
+                    let (net_x, net_y) = camera.to_network(x, y);
+                    if let Some(node) = network.node_at(net_x, net_y) {
+                        if node != network.focus {
+                            network.focus = node;
+                            hud.selection = None;
+                        }
+                        let (origin_x, origin_y) = network.origin(node);
+                        hud.selection = inspector::pick(network.focused(), net_x - origin_x, net_y - origin_y);
+                    }
+                }
+
+                Event::MouseButtonDown { window_id, mouse_btn: MouseButton::Right, x, y, .. }
+                    if window_id == main_window_id =>
+                {
+                    drag_from = Some((x, y));
+                }
+                Event::MouseButtonUp { mouse_btn: MouseButton::Right, .. } => drag_from = None,
+                Event::MouseMotion { window_id, x, y, .. } if window_id == main_window_id => {
+                    if let Some((from_x, from_y)) = drag_from {
+                        camera.pan(x - from_x, y - from_y);
+                        drag_from = Some((x, y));
+                    }
+                }
+                Event::MouseWheel { window_id, y, mouse_x, mouse_y, .. } if window_id == main_window_id => {
+                    camera.zoom_at(mouse_x, mouse_y, y);
+                }
+
                 Event::KeyDown { keycode: Some(key), .. } => match key {
                     Keycode::Escape => { 
-                        show_statistics(&intersection, &sdl_context, &mut event_pump);
+                        print_reports(&network);
+                        show_statistics(network.focused(), &sdl_context, &mut event_pump);
                         break 'running
                     },
                     Keycode::Down | Keycode::S => {
-                        intersection.add_car_in(Direction::South);
+                        network.add_car_in(Direction::South);
                     }
                     Keycode::Up | Keycode::W => {
-                        intersection.add_car_in(Direction::North);
+                        network.add_car_in(Direction::North);
                     }
                     Keycode::Left | Keycode::A => {
-                        intersection.add_car_in(Direction::West);
+                        network.add_car_in(Direction::West);
                     }
                     Keycode::Right | Keycode::D => {
-                        intersection.add_car_in(Direction::East);
+                        network.add_car_in(Direction::East);
                     }
                     Keycode::R => {
-                        intersection.add_car_in_rnd();
+                        network.add_car_in_rnd();
+                    }
+                    Keycode::E => {
+                        network.add_emergency_vehicle();
+                    }
+                    Keycode::H => hud.toggle(),
+                    Keycode::G => {
+                        gantt = match gantt {
+                            Some(_) => None,
+                            None => ttf_context
+                                .load_font(FONT_PATH, HUD_FONT_SIZE)
+                                .and_then(|font| GanttView::open(&video_subsystem, font))
+                                .inspect_err(|e| println!("Failed to open reservation chart: {}", e))
+                                .ok(),
+                        };
+                    }
+                    Keycode::Space => clock.toggle_pause(),
+                    Keycode::Period => clock.step(),
+                    Keycode::Equals | Keycode::Plus | Keycode::KpPlus => {
+                        clock.faster();
+                        println!("Time scale x{}", clock.scale());
+                    }
+                    Keycode::Minus | Keycode::KpMinus => {
+                        clock.slower();
+                        println!("Time scale x{}", clock.scale());
                     }
                     _ => {} // Ignore other keys
                 },
@@ -84,15 +195,239 @@ pub fn main() {
             }
         }
 
-        intersection.update();
+        for _ in 0..clock.ticks(frame_time) {
+            tick(&mut network, &mut arrivals, &mut pedestrian_arrivals, &mut emergency_arrivals, &mut time_series);
+        }
 
-        present_main_canvas(&mut canvas, &bg_texture, &intersection);
+        hud.record(&network, &clock);
+        present_main_canvas(&mut canvas, &texture_creator, &textures.background, &network, &camera, &hud);
+        if let Some(view) = gantt.as_mut()
+            && let Err(e) = view.present(network.focused())
+        {
+            println!("Closed reservation chart: {}", e);
+            gantt = None;
+        }
 
         let elapsed = frame_start.elapsed();
+        if let Some(server) = &metrics_server {
+            server.publish(&network, elapsed);
+        }
         if elapsed < target_frame_duration {
             std::thread::sleep(target_frame_duration - elapsed);
         }
     }
+
+    if let Some(writer) = time_series.as_mut() {
+        writer.flush().unwrap_or_else(|e| println!("Failed to flush time series: {}", e));
+    }
+}
+
+/// Headless run: draw every `--frame-ticks`th tick to an offscreen surface and save
+/// it as `<dir>/frame_00000.png` and so on, until `--frames` frames are written.
+fn render_frames(dir: &str, ttf_context: &Sdl2TtfContext) {
+    let frames: usize = arg_value("--frames")
+        .map(|n| n.parse().expect("--frames takes a number of frames"))
+        .unwrap_or(RENDER_FRAMES);
+    let frame_ticks: usize = arg_value("--frame-ticks")
+        .map(|n| n.parse().expect("--frame-ticks takes a number of ticks"))
+        .unwrap_or(RENDER_FRAME_TICKS)
+        .max(1);
+    std::fs::create_dir_all(dir).unwrap_or_else(|e| panic!("Failed to create {}: {}", dir, e));
+
+    let surface = Surface::new(SIMULATION_WINDOW_WIDTH, SIMULATION_WINDOW_HEIGHT, PixelFormatEnum::RGBA32).unwrap();
+    let mut canvas = surface.into_canvas().unwrap();
+    let texture_creator = canvas.texture_creator();
+    let textures = Textures::load(&texture_creator);
+
+    let road_map = road_map_from_args();
+    let mut network = network_from_args(&road_map, textures.by_route());
+    let (network_width, network_height) = network.size();
+    let camera = Camera::fit(network_width, network_height);
+    let mut arrivals = arrivals_from_args();
+    let mut pedestrian_arrivals = pedestrian_arrivals_from_args();
+    let mut emergency_arrivals = emergency_arrivals_from_args();
+    let mut time_series = time_series_from_args();
+    let mut hud = Hud::new(ttf_context.load_font(FONT_PATH, HUD_FONT_SIZE).unwrap());
+    let clock = SimClock::new();
+
+    for frame in 0..frames {
+        for _ in 0..frame_ticks {
+            tick(&mut network, &mut arrivals, &mut pedestrian_arrivals, &mut emergency_arrivals, &mut time_series);
+        }
+        hud.record(&network, &clock);
+        present_main_canvas(&mut canvas, &texture_creator, &textures.background, &network, &camera, &hud);
+
+        let path = format!("{}/frame_{:05}.png", dir, frame);
+        canvas
+            .surface()
+            .convert_format(PixelFormatEnum::RGBA32)
+            .and_then(|frame| frame.save(&path))
+            .unwrap_or_else(|e| panic!("Failed to save {}: {}", path, e));
+    }
+
+    if let Some(writer) = time_series.as_mut() {
+        writer.flush().unwrap_or_else(|e| println!("Failed to flush time series: {}", e));
+    }
+    print_reports(&network);
+    println!("Wrote {} frames to {}", frames, dir);
+}
+
+/// Advance the simulation by one tick: release scheduled arrivals of cars,
+/// pedestrians and emergency vehicles, move everything along and sample the time series.
+fn tick(
+    network: &mut Network,
+    arrivals: &mut Option<FixedArrivals>,
+    pedestrian_arrivals: &mut Option<FixedArrivals>,
+    emergency_arrivals: &mut Option<FixedArrivals>,
+    time_series: &mut Option<TimeSeriesWriter>,
+) {
+    if let Some(arrivals) = arrivals.as_mut() {
+        for _ in 0..arrivals.due(network.elapsed()) {
+            network.add_car_in_rnd();
+        }
+    }
+    if let Some(arrivals) = pedestrian_arrivals.as_mut() {
+        for _ in 0..arrivals.due(network.elapsed()) {
+            network.add_pedestrians();
+        }
+    }
+    if let Some(arrivals) = emergency_arrivals.as_mut() {
+        for _ in 0..arrivals.due(network.elapsed()) {
+            network.add_emergency_vehicle();
+        }
+    }
+
+    network.update(BASE_DELTA_TIME);
+
+    if let Some(writer) = time_series.as_mut()
+        && let Err(e) = writer.sample(network)
+    {
+        println!("Stopped writing time series: {}", e);
+        *time_series = None;
+    }
+}
+
+/// Background and per-route car textures, loaded for whichever canvas draws them.
+struct Textures<'a> {
+    background: Texture<'a>,
+    cars: [(Route, Texture<'a>); 3],
+}
+
+impl<'a> Textures<'a> {
+    fn load<T>(texture_creator: &'a TextureCreator<T>) -> Self {
+        Textures {
+            background: texture_creator.load_texture("assets/bg.png").unwrap(),
+            cars: [
+                (Route::Right, texture_creator.load_texture("assets/car_r.png").unwrap()),
+                (Route::Left, texture_creator.load_texture("assets/car_l.png").unwrap()),
+                (Route::Straight, texture_creator.load_texture("assets/car_s.png").unwrap()),
+            ],
+        }
+    }
+
+    fn by_route(&self) -> HashMap<Route, &Texture<'a>> {
+        self.cars.iter().map(|(route, texture)| (*route, texture)).collect()
+    }
+}
+
+/// Delay and movement reports for every intersection of the network.
+fn print_reports(network: &Network) {
+    for (node, intersection) in network.nodes.iter().enumerate() {
+        if network.nodes.len() > 1 {
+            println!("Intersection {}", network.names[node]);
+        }
+        println!("{}", metrics::delay_report(&intersection.cars_out));
+        println!("{}", intersection.get_movement_report());
+        if !intersection.layout.crosswalks.is_empty() {
+            println!("{}", metrics::pedestrian_report(&intersection.pedestrians_out));
+        }
+        if intersection.cars_out.iter().any(|car| car.class == VehicleClass::Emergency) {
+            println!("{}", metrics::emergency_report(&intersection.cars_out));
+        }
+    }
+}
+
+/// Road map from `--map`, or a `--grid` of standard intersections (one by default).
+fn road_map_from_args() -> RoadMap {
+    if let Some(path) = arg_value("--map") {
+        return RoadMap::load(&path).unwrap_or_else(|e| panic!("Failed to load map {}: {}", path, e));
+    }
+    let (rows, columns) = match arg_value("--grid") {
+        Some(grid) => grid
+            .split_once('x')
+            .and_then(|(rows, columns)| Some((rows.parse().ok()?, columns.parse().ok()?)))
+            .expect("--grid takes rows and columns, e.g. 2x3"),
+        None => (1, 1),
+    };
+    RoadMap::grid(rows.max(1), columns.max(1))
+}
+
+fn network_from_args<'a>(road_map: &'a RoadMap, car_textures: HashMap<Route, &'a Texture<'a>>) -> Network<'a> {
+    let od_matrix = match arg_value("--od") {
+        Some(path) => OdMatrix::load(&path)
+            .unwrap_or_else(|e| panic!("Failed to load OD matrix {}: {}", path, e)),
+        None => OdMatrix::uniform(),
+    };
+    let queue_capacity = arg_value("--queue-capacity")
+        .map(|n| n.parse().expect("--queue-capacity takes a number of cars"));
+
+    let mut network = Network::new(road_map, car_textures, od_matrix, queue_capacity);
+    network.prebook = !std::env::args().any(|arg| arg == "--no-prebook");
+    if let Some(share) = arg_value("--av-share") {
+        let share: f64 = share.parse().expect("--av-share takes a fraction between 0 and 1");
+        network.av_share = share.clamp(0.0, 1.0);
+    }
+    if let Some(share) = arg_value("--bus-share") {
+        let share: f64 = share.parse().expect("--bus-share takes a fraction between 0 and 1");
+        network.bus_share = share.clamp(0.0, 1.0);
+    }
+    if let Some(share) = arg_value("--freight-share") {
+        let share: f64 = share.parse().expect("--freight-share takes a fraction between 0 and 1");
+        network.freight_share = share.clamp(0.0, 1.0 - network.bus_share);
+    }
+    // Both radio links of every intersection, cars to manager and back, behave alike
+    let loss: Option<f64> = arg_value("--v2i-loss").map(|p| p.parse().expect("--v2i-loss takes a fraction between 0 and 1"));
+    let latency = arg_value("--v2i-latency")
+        .map(|ms| Duration::from_millis(ms.parse().expect("--v2i-latency takes milliseconds")));
+    for node in network.nodes.iter_mut() {
+        for channel in [&mut node.v2i.uplink, &mut node.v2i.downlink] {
+            channel.loss = loss.map_or(channel.loss, |loss| loss.clamp(0.0, 1.0));
+            channel.latency = latency.unwrap_or(channel.latency);
+        }
+    }
+    network
+}
+
+fn time_series_from_args() -> Option<TimeSeriesWriter> {
+    let csv_interval = arg_value("--csv-interval")
+        .map(|ms| Duration::from_millis(ms.parse().expect("--csv-interval takes milliseconds")))
+        .unwrap_or(CSV_SAMPLE_INTERVAL);
+    arg_value("--csv").map(|path| {
+        TimeSeriesWriter::create(&path, csv_interval)
+            .unwrap_or_else(|e| panic!("Failed to create {}: {}", path, e))
+    })
+}
+
+fn arrivals_from_args() -> Option<FixedArrivals> {
+    arg_value("--spawn-every")
+        .map(|ms| FixedArrivals::new(Duration::from_millis(ms.parse().expect("--spawn-every takes milliseconds"))))
+}
+
+/// One pedestrian per intersection with crosswalks every `--pedestrians-every` ms.
+fn pedestrian_arrivals_from_args() -> Option<FixedArrivals> {
+    arg_value("--pedestrians-every")
+        .map(|ms| FixedArrivals::new(Duration::from_millis(ms.parse().expect("--pedestrians-every takes milliseconds"))))
+}
+
+/// One emergency vehicle entering the network every `--emergency-every` ms.
+fn emergency_arrivals_from_args() -> Option<FixedArrivals> {
+    arg_value("--emergency-every")
+        .map(|ms| FixedArrivals::new(Duration::from_millis(ms.parse().expect("--emergency-every takes milliseconds"))))
+}
+
+/// Value following `flag` on the command line, e.g. `--od demand.txt`.
+fn arg_value(flag: &str) -> Option<String> {
+    std::env::args().skip_while(|arg| arg != flag).nth(1)
 }
 
 use sdl2::ttf::Font;
@@ -118,8 +453,7 @@ fn show_statistics(
     let mut canvas: Canvas<Window> = window.into_canvas().build().unwrap();
     let texture_creator = canvas.texture_creator();
 
-    let font_path = "assets/Roboto-Regular.ttf"; // Change if needed
-    let font: Font = ttf_context.load_font(font_path, FONT_SIZE).unwrap();
+    let font: Font = ttf_context.load_font(FONT_PATH, FONT_SIZE).unwrap();
 
     let stats_text = intersection.get_statistics(); // should return String
 
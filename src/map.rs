@@ -0,0 +1,765 @@
+use crate::consts::*;
+use crate::crossing_manager::{PathZone, ZoneIndex};
+use crate::intersection::{Direction, Lane, Route};
+use crate::utils::{Body, along, destination_of, footprint, polygon_spans, route_between, target_lane};
+use sdl2::rect::{Point, Rect};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::f64::consts::TAU;
+use std::fs;
+
+/// The 900x900 four-way layout the simulator was built around.
+const STANDARD_LAYOUT: &str = include_str!("../assets/maps/standard.json");
+
+/// Where cars of one lane of an approach appear, and how fast they start.
+#[derive(Clone, Deserialize)]
+pub struct SpawnPoint {
+    pub heading: Direction,
+    pub lane: Lane,
+    pub x: i32,
+    pub y: i32,
+    pub speed: i32,
+}
+
+/// Zones a movement crosses, in order, overriding the traced path. Without `lane`
+/// it applies to every lane of the approach that has no entry of its own.
+#[derive(Clone, Deserialize)]
+pub struct ZonePath {
+    pub heading: Direction,
+    pub route: Route,
+    #[serde(default)]
+    pub lane: Option<Lane>,
+    pub zones: Vec<ZoneIndex>,
+}
+
+/// Zebra crossing over the arm cars arriving heading `approach` come in on: a
+/// straight run of zone cells from one kerb to the other. Pedestrians book the
+/// cells as a car books its path, and cars whose path runs through them book
+/// round the people walking.
+#[derive(Clone, Deserialize)]
+pub struct Crosswalk {
+    pub approach: Direction,
+    pub zones: Vec<ZoneIndex>,
+}
+
+/// Single circulating lane of a roundabout, split into equal sectors that act as
+/// the zones. Traffic goes round anticlockwise on screen. Cars join one sector
+/// before the centre line of their arm and leave one sector past the centre line
+/// of the exit arm, so leaving and joining traffic keep to their own side.
+#[derive(Clone, Deserialize)]
+pub struct Roundabout {
+    pub center: (i32, i32),
+    /// Radius of the centre line of the circulating lane.
+    pub radius: i32,
+    pub lane_width: u32,
+    /// At least twelve, and enough that every arm meets the ring on a sector
+    /// boundary and every movement, less the sectors it joins and leaves by,
+    /// still passes one: a multiple of four for arms along the screen axes, of
+    /// eight with diagonal ones.
+    pub sectors: usize,
+}
+
+impl Roundabout {
+    /// Screen angle (y down) at which the centre line of the arm cars arriving
+    /// with `heading` come in on meets the ring: the side they come from.
+    fn arm_angle(heading: Direction) -> f64 {
+        let (dx, dy) = heading.vector();
+        (-dy as f64).atan2(-dx as f64).rem_euclid(TAU)
+    }
+
+    fn entry_angle(&self, heading: Direction) -> f64 {
+        Self::arm_angle(heading) - self.sector_angle()
+    }
+
+    fn sector_angle(&self) -> f64 {
+        TAU / self.sectors as f64
+    }
+
+    fn meets_boundary(&self, heading: Direction) -> bool {
+        let sectors = Self::arm_angle(heading) / self.sector_angle();
+        (sectors - sectors.round()).abs() < 1e-6
+    }
+
+    /// Distance round the ring from joining it from `heading` to leaving it on
+    /// `route`: the angle between the two arms, less the sectors joined and left by.
+    pub fn arc_length(&self, heading: Direction, route: Route) -> f64 {
+        let exit = destination_of(heading, route);
+        let turn = (Self::arm_angle(heading) - Self::arm_angle(exit.opposite())).rem_euclid(TAU);
+        (turn - 2.0 * self.sector_angle()) * self.radius as f64
+    }
+
+    /// Sectors passed from the entry of `heading` on `route`, in order.
+    fn path(&self, heading: Direction, route: Route) -> Vec<ZoneIndex> {
+        let first = (self.entry_angle(heading).rem_euclid(TAU) / self.sector_angle()).round() as usize;
+        let count = (self.arc_length(heading, route) / self.radius as f64 / self.sector_angle()).round() as usize;
+        (1..=count).map(|i| (0, (first + self.sectors - i) % self.sectors)).collect()
+    }
+
+    /// Where a car leaving heading `exit` comes off the centre line.
+    pub fn exit_point(&self, exit: Direction) -> (f64, f64) {
+        self.point_at(Self::arm_angle(exit.opposite()) + self.sector_angle())
+    }
+
+    fn point_at(&self, angle: f64) -> (f64, f64) {
+        let (sin, cos) = angle.sin_cos();
+        let (cx, cy) = self.center;
+        (cx as f64 + self.radius as f64 * cos, cy as f64 + self.radius as f64 * sin)
+    }
+
+    /// Point on the centre line `travelled` px after joining from `heading`, and the
+    /// heading closest to the direction of travel there.
+    pub fn point(&self, heading: Direction, travelled: f64) -> ((f64, f64), Direction) {
+        let angle = self.entry_angle(heading) - travelled / self.radius as f64;
+        let (sin, cos) = angle.sin_cos();
+        let position = self.point_at(angle);
+
+        // Going anticlockwise on screen the car moves along (sin, -cos)
+        (position, Direction::nearest(sin, -cos))
+    }
+
+    /// Points along the middle of a sector, `steps` segments long.
+    fn sector_arc(&self, sector: usize, radius: f64, steps: usize) -> impl Iterator<Item = Point> + '_ {
+        let start = sector as f64 * self.sector_angle();
+        let (cx, cy) = self.center;
+        (0..=steps).map(move |i| {
+            let (sin, cos) = (start + self.sector_angle() * i as f64 / steps as f64).sin_cos();
+            Point::new(cx + (radius * cos).round() as i32, cy + (radius * sin).round() as i32)
+        })
+    }
+
+    fn inner_radius(&self) -> f64 {
+        self.radius as f64 - self.lane_width as f64 / 2.0
+    }
+
+    fn outer_radius(&self) -> f64 {
+        self.radius as f64 + self.lane_width as f64 / 2.0
+    }
+
+    /// Horizontal lines filling the island inside the ring, joined end to end.
+    pub fn island_spans(&self) -> Vec<Point> {
+        let radius = self.inner_radius() as i32;
+        let (cx, cy) = self.center;
+        (-radius..=radius)
+            .flat_map(|dy| {
+                let half = ((radius * radius - dy * dy) as f64).sqrt() as i32;
+                let (left, right) = (Point::new(cx - half, cy + dy), Point::new(cx + half, cy + dy));
+                // Alternate the direction so the joins stay inside the island
+                if dy % 2 == 0 { [left, right] } else { [right, left] }
+            })
+            .collect()
+    }
+
+    fn sector_at(&self, x: i32, y: i32) -> Option<usize> {
+        let (dx, dy) = ((x - self.center.0) as f64, (y - self.center.1) as f64);
+        let distance = dx.hypot(dy);
+        if distance < self.inner_radius() || distance > self.outer_radius() {
+            return None;
+        }
+        Some((dy.atan2(dx).rem_euclid(TAU) / self.sector_angle()) as usize % self.sectors)
+    }
+}
+
+/// Geometry of one intersection tile, in tile pixels with the origin top-left.
+/// The approaches are the headings that have spawn points, so leaving out an arm
+/// makes a T-junction, and diagonal headings add arms between the axis ones, up
+/// to eight. Entry lines are the edges of the zone grid square to each heading;
+/// a turning car swings onto the spawn lane it would use had it started on the
+/// approach it turns into where the two lanes cross, and the zones a movement
+/// crosses are traced along that path.
+/// A layout with a `roundabout` has ring sectors instead of a zone grid.
+/// `crosswalks` are cells of the zone grid; a grid with crosswalks usually has a
+/// ring of cells round the box for them, and right turns then book zones too.
+#[derive(Clone, Deserialize)]
+pub struct Layout {
+    pub width: u32,
+    pub height: u32,
+    /// Top-left corner of the zone grid.
+    #[serde(default)]
+    pub zone_origin: (i32, i32),
+    #[serde(default)]
+    pub zone_size: u32,
+    #[serde(default)]
+    pub zone_rows: usize,
+    #[serde(default)]
+    pub zone_columns: usize,
+    /// Gap between the zone grid and the drawn outline of the intersection box.
+    #[serde(default)]
+    pub box_margin: i32,
+    #[serde(default)]
+    pub roundabout: Option<Roundabout>,
+    pub spawn_points: Vec<SpawnPoint>,
+    #[serde(default)]
+    pub zone_paths: Vec<ZonePath>,
+    #[serde(default)]
+    pub crosswalks: Vec<Crosswalk>,
+}
+
+impl Layout {
+    pub fn standard() -> Self {
+        serde_json::from_str(STANDARD_LAYOUT).expect("Built-in layout is invalid")
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        let approaches: Vec<Direction> = self.approaches().collect();
+        if approaches.len() < 2 {
+            return Err("a layout needs at least two approaches".to_string());
+        }
+        for &heading in &approaches {
+            for lane in Lane::ALL {
+                if !self.spawn_points.iter().any(|p| p.heading == heading && p.lane == lane) {
+                    return Err(format!("no spawn point for the {:?} lane heading {:?}", lane, heading));
+                }
+            }
+        }
+        match &self.roundabout {
+            Some(ring) if ring.sectors < 12 => {
+                return Err(format!("a roundabout needs at least twelve sectors, not {}", ring.sectors));
+            }
+            Some(ring) if ring.inner_radius() <= 0.0 => {
+                return Err("the roundabout lane is wider than its radius allows".to_string());
+            }
+            None if self.zone_rows == 0 || self.zone_columns == 0 || self.zone_size == 0 => {
+                return Err("a layout needs a zone grid or a roundabout".to_string());
+            }
+            _ => {}
+        }
+        if let Some(ring) = &self.roundabout {
+            if let Some(heading) = approaches.iter().find(|&&heading| !ring.meets_boundary(heading)) {
+                return Err(format!("the {:?} arm does not meet the roundabout on a sector boundary with {} sectors", heading, ring.sectors));
+            }
+            if let Some((heading, route)) = self.movements().find(|&(heading, route)| ring.path(heading, route).is_empty()) {
+                return Err(format!("{:?} {:?} passes no sector of the roundabout with {} sectors", heading, route, ring.sectors));
+            }
+        }
+        for path in &self.zone_paths {
+            if !self.allows(path.heading, destination_of(path.heading, path.route)) {
+                return Err(format!("zone path for {:?} {:?} is not a movement of this layout", path.heading, path.route));
+            }
+            if let Some(zone) = path.zones.iter().find(|zone| !self.zones().any(|z| z == **zone)) {
+                return Err(format!("zone {:?} of the {:?} {:?} path is outside the grid", zone, path.heading, path.route));
+            }
+        }
+        if !self.crosswalks.is_empty() && self.roundabout.is_some() {
+            return Err("crosswalks need a zone grid, not a roundabout".to_string());
+        }
+        for crosswalk in &self.crosswalks {
+            if !self.has_approach(crosswalk.approach) {
+                return Err(format!("crosswalk over the {:?} approach, which the layout does not have", crosswalk.approach));
+            }
+            if crosswalk.approach.is_diagonal() {
+                return Err(format!("crosswalk over the {:?} approach: only arms along the screen axes can have one", crosswalk.approach));
+            }
+            if crosswalk.approach.is_diagonal() {
+                return Err(format!("crosswalk over the {:?} approach: only arms along the screen axes can have one", crosswalk.approach));
+            }
+            if let Some(zone) = crosswalk.zones.iter().find(|zone| !self.zones().any(|z| z == **zone)) {
+                return Err(format!("zone {:?} of the {:?} crosswalk is outside the grid", zone, crosswalk.approach));
+            }
+            let steps: Vec<(isize, isize)> = crosswalk
+                .zones
+                .windows(2)
+                .map(|pair| (pair[1].0 as isize - pair[0].0 as isize, pair[1].1 as isize - pair[0].1 as isize))
+                .collect();
+            // One cell at a time, square to the traffic on the arm
+            let (dx, dy) = crosswalk.approach.vector();
+            let across = |(rows, cols): (isize, isize)| {
+                rows.abs() + cols.abs() == 1 && cols * dx as isize + rows * dy as isize == 0
+            };
+            if crosswalk.zones.is_empty() || !steps.iter().all(|&step| across(step) && step == steps[0]) {
+                return Err(format!("the {:?} crosswalk must be a straight run of cells across its arm", crosswalk.approach));
+            }
+        }
+        Ok(())
+    }
+
+    /// Headings cars can arrive with.
+    pub fn approaches(&self) -> impl Iterator<Item = Direction> + '_ {
+        Direction::ALL.into_iter().filter(|&heading| self.has_approach(heading))
+    }
+
+    pub fn has_approach(&self, heading: Direction) -> bool {
+        self.spawn_points.iter().any(|p| p.heading == heading)
+    }
+
+    pub fn has_diagonal_arms(&self) -> bool {
+        self.approaches().any(Direction::is_diagonal)
+    }
+
+    /// Headings and routes of every movement the layout allows.
+    fn movements(&self) -> impl Iterator<Item = (Direction, Route)> + '_ {
+        self.approaches()
+            .flat_map(|heading| Route::ALL.map(|route| (heading, route)))
+            .filter(|&(heading, route)| self.allows(heading, destination_of(heading, route)))
+    }
+
+    /// Whether a car arriving heading `origin` can leave heading `destination`:
+    /// both arms must exist, and every arm carries traffic both ways.
+    pub fn allows(&self, origin: Direction, destination: Direction) -> bool {
+        self.has_approach(origin)
+            && self.has_approach(destination.opposite())
+            && route_between(origin, destination).is_some()
+    }
+
+    /// Right turns on a zone grid keep to the curb lane, clear of the zones, and
+    /// only follow the car ahead; on a roundabout every movement uses the ring,
+    /// with crosswalks right turns have to give way to pedestrians, and with
+    /// diagonal arms other movements turn into the same exit lanes.
+    pub fn bypasses_zones(&self, route: Route) -> bool {
+        route == Route::Right && self.roundabout.is_none() && self.crosswalks.is_empty() && !self.has_diagonal_arms()
+    }
+
+    /// Lane a car starting in `from` has to be in by the entry line. Every lane of
+    /// a roundabout arm feeds the ring, so there cars stay where they are.
+    pub fn target_lane(&self, route: Route, from: Lane) -> Lane {
+        match self.roundabout {
+            Some(_) => from,
+            None => target_lane(route, from),
+        }
+    }
+
+    pub fn zone_length(&self) -> f64 {
+        match &self.roundabout {
+            Some(ring) => ring.radius as f64 * ring.sector_angle(),
+            None => self.zone_size as f64,
+        }
+    }
+
+    /// Zone cells row by row; a roundabout has a single row of sectors.
+    pub fn zones(&self) -> impl Iterator<Item = ZoneIndex> + '_ {
+        let (rows, columns) = match &self.roundabout {
+            Some(ring) => (1, ring.sectors),
+            None => (self.zone_rows, self.zone_columns),
+        };
+        (0..rows).flat_map(move |row| (0..columns).map(move |col| (row, col)))
+    }
+
+    /// Distance from the tile edge a car arriving with `heading` comes in from to
+    /// the tile edge it leaves by.
+    pub fn route_length(&self, heading: Direction, route: Route) -> i32 {
+        let exit = destination_of(heading, route);
+        let Some(ring) = &self.roundabout else {
+            // Along the lane the route is driven in up to where it crosses the exit lane
+            let lane = self.target_lane(route, Lane::Middle);
+            let turn_at = self.crossing_point(heading, lane, route).unwrap_or(self.car_body(heading, lane).center());
+            let approach_leg = dot(turn_at, heading.unit()) - self.tile_along(heading).0;
+            let exit_leg = self.tile_along(exit).1 - dot(turn_at, exit.unit());
+            return (approach_leg + exit_leg).round() as i32;
+        };
+        let exit_leg = self.tile_along(exit).1 - dot(ring.exit_point(exit), exit.unit());
+        self.entry_distance(heading) + ring.arc_length(heading, route).round() as i32 + exit_leg.round() as i32
+    }
+
+    /// Spawn position and starting speed of a lane.
+    pub fn spawn_position(&self, heading: Direction, lane: Lane) -> (i32, i32, i32) {
+        self.spawn_points
+            .iter()
+            .find(|p| p.heading == heading && p.lane == lane)
+            .map(|p| (p.x, p.y, p.speed))
+            .expect("Layout has no spawn point for lane")
+    }
+
+    /// Top-left corner of a car in `lane` heading `heading` as it enters the tile.
+    /// Leaving by an arm nobody arrives on, e.g. the stem of a T-junction, it is the
+    /// same lane of the opposite approach turned half round the tile centre.
+    fn lane_position(&self, heading: Direction, lane: Lane) -> (i32, i32) {
+        if self.has_approach(heading) {
+            let (x, y, _) = self.spawn_position(heading, lane);
+            return (x, y);
+        }
+        let (x, y, _) = self.spawn_position(heading.opposite(), lane);
+        let (width, height) = footprint(heading, CAR_WIDTH_PX, CAR_HEIGHT_PX);
+        (self.width as i32 - x - width as i32, self.height as i32 - y - height as i32)
+    }
+
+    /// Zones a car in `lane` heading `heading` crosses on `route`, in the order it reaches them.
+    pub fn zone_path(&self, heading: Direction, lane: Lane, route: Route) -> Vec<ZoneIndex> {
+        self.timed_zone_path(heading, lane, route).into_iter().map(|zone| zone.zone).collect()
+    }
+
+    /// Zones on a path with how far the front of the car has come from the entry
+    /// line as it reaches and leaves each. Paths given in the layout and round a
+    /// ring take a zone length per zone.
+    pub fn timed_zone_path(&self, heading: Direction, lane: Lane, route: Route) -> Vec<PathZone> {
+        let matching = || self.zone_paths.iter().filter(|p| p.heading == heading && p.route == route);
+        matching()
+            .find(|p| p.lane == Some(lane))
+            .or_else(|| matching().find(|p| p.lane.is_none()))
+            .map(|p| PathZone::in_turn(&p.zones, self.zone_length()))
+            .unwrap_or_else(|| match &self.roundabout {
+                Some(ring) => PathZone::in_turn(&ring.path(heading, route), self.zone_length()),
+                None => self.trace_zone_path(heading, lane, route),
+            })
+    }
+
+    /// Zones swept by the body of a car driving `lane` heading `heading` up to
+    /// where it turns, then along its exit lane, in the order it reaches them.
+    fn trace_zone_path(&self, heading: Direction, lane: Lane, route: Route) -> Vec<PathZone> {
+        let start = self.car_body(heading, lane);
+        let entry = self.entry_distance(heading) as f64;
+        let travelled = self.distance_travelled(&start) as f64;
+        let turn = self
+            .turn_distance(heading, lane, route)
+            .map(|at| (at - entry, self.body_after_turn(&start.moved(at - travelled), route, 0.0)));
+
+        let mut path: Vec<PathZone> = self
+            .zones()
+            .filter_map(|zone| {
+                let rect = self.zone_rect(zone);
+                let approach = start.sweep(rect).map(|(from, to)| (from + travelled - entry, to + travelled - entry));
+                let (approach, exit) = match turn {
+                    Some((at, turned)) => (
+                        approach.filter(|&(from, _)| from < at).map(|(from, to)| (from, to.min(at))),
+                        turned.sweep(rect).filter(|&(_, to)| to > 0.0).map(|(from, to)| (at + from.max(0.0), at + to)),
+                    ),
+                    None => (approach, None),
+                };
+                let (reach, clear) = match (approach, exit) {
+                    (Some(before), Some(after)) => (before.0.min(after.0), before.1.max(after.1)),
+                    (Some(swept), None) | (None, Some(swept)) => swept,
+                    (None, None) => return None,
+                };
+                Some(PathZone { zone, reach: reach.max(0.0), leave: clear - start.length as f64 })
+            })
+            .collect();
+        path.sort_by(|a, b| a.reach.total_cmp(&b.reach));
+        path
+    }
+
+    fn tile_rect(&self) -> Rect {
+        Rect::new(0, 0, self.width, self.height)
+    }
+
+    /// Extent of the tile along a heading, from the edge cars come in by to the one they leave by.
+    fn tile_along(&self, heading: Direction) -> (f64, f64) {
+        let (from, to) = along(self.tile_rect(), heading);
+        (from as f64, to as f64)
+    }
+
+    /// Body of a car entering the tile in a lane.
+    pub fn car_body(&self, heading: Direction, lane: Lane) -> Body {
+        let (x, y) = self.lane_position(heading, lane);
+        Body::new(x, y, heading, CAR_WIDTH_PX, CAR_HEIGHT_PX)
+    }
+
+    /// Lane a route leaves by. Each side gets its own, as cars turning in from
+    /// different approaches do not follow one another.
+    fn exit_lane(route: Route) -> Lane {
+        match route.side() {
+            Route::Right => Lane::Curb,
+            Route::Left => Lane::Median,
+            _ => Lane::Middle,
+        }
+    }
+
+    /// Where the centre line of `lane` heading `heading` crosses that of the lane
+    /// `route` leaves by; `None` going straight on.
+    fn crossing_point(&self, heading: Direction, lane: Lane, route: Route) -> Option<(f64, f64)> {
+        let exit = destination_of(heading, route);
+        if exit == heading {
+            return None;
+        }
+        let (px, py) = self.car_body(heading, lane).center();
+        let (qx, qy) = self.car_body(exit, Self::exit_lane(route)).center();
+        let (u, v) = (heading.unit(), exit.unit());
+        let t = cross((qx - px, qy - py), v) / cross(u, v);
+        Some((px + t * u.0, py + t * u.1))
+    }
+
+    /// How far the front of a car in `lane` has come from the tile edge when it
+    /// swings onto the exit lane of `route`: level with the far side of a car
+    /// standing where the two lanes cross, facing the way out.
+    pub fn turn_distance(&self, heading: Direction, lane: Lane, route: Route) -> Option<f64> {
+        let crossing = self.crossing_point(heading, lane, route)?;
+        let (u, v) = (heading.unit(), destination_of(heading, route).unit());
+        let reach = (dot(u, v).abs() * CAR_HEIGHT_PX as f64 + cross(u, v).abs() * CAR_WIDTH_PX as f64) / 2.0;
+        Some(dot(crossing, u) + reach - self.tile_along(heading).0)
+    }
+
+    /// `body` swung onto the exit lane of `route` and moved `distance` px on,
+    /// starting with its rear level with the back of the body it turned from.
+    pub fn body_after_turn(&self, body: &Body, route: Route, distance: f64) -> Body {
+        let exit = destination_of(body.heading, route);
+        let rear = body.extent_along(exit).0 + distance;
+        self.exit_body(exit, route, rear, body.width, body.length)
+    }
+
+    /// Body centred on the exit lane of `route` heading `exit`, its rear at `rear` along it.
+    fn exit_body(&self, exit: Direction, route: Route, rear: f64, width: u32, length: u32) -> Body {
+        let lane = self.car_body(exit, Self::exit_lane(route)).center();
+        let u = exit.unit();
+        let shift = rear + length as f64 / 2.0 - dot(lane, u);
+        Body::centred((lane.0 + shift * u.0, lane.1 + shift * u.1), exit, width, length)
+    }
+
+    /// Horizontal runs of pixels covering the road, one list per arm and one for
+    /// the box, for layouts the four-way background picture does not fit.
+    pub fn road_spans(&self) -> Vec<Vec<Point>> {
+        let middle = (self.width as f64 / 2.0, self.height as f64 / 2.0);
+        let mut areas: Vec<Vec<(f64, f64)>> = self
+            .approaches()
+            .map(|heading| {
+                let (u, r) = (heading.unit(), (-heading.unit().1, heading.unit().0));
+                let mut lanes: Vec<f64> = Lane::ALL
+                    .into_iter()
+                    .flat_map(|lane| [self.car_body(heading, lane), self.car_body(heading.opposite(), lane)])
+                    .map(|body| dot(body.center(), r))
+                    .collect();
+                lanes.sort_by(f64::total_cmp);
+                // The road reaches half a lane past the outer lane lines
+                let half_lane = lanes.windows(2).map(|pair| pair[1] - pair[0]).fold(f64::MAX, f64::min) / 2.0;
+                let (left, right) = (lanes[0] - half_lane, lanes[lanes.len() - 1] + half_lane);
+                let (from, to) = (self.tile_along(heading).0, dot(middle, u));
+                [(from, left), (to, left), (to, right), (from, right)]
+                    .map(|(along, across)| (along * u.0 + across * r.0, along * u.1 + across * r.1))
+                    .to_vec()
+            })
+            .collect();
+        let outline = self.box_rect();
+        areas.push(
+            [outline.top_left(), outline.top_right(), outline.bottom_right(), outline.bottom_left()]
+                .map(|corner| (corner.x() as f64, corner.y() as f64))
+                .to_vec(),
+        );
+        let height = self.height as i32;
+        areas
+            .iter()
+            .map(|area| polygon_spans(area).into_iter().filter(|point| (0..height).contains(&point.y())).collect())
+            .collect()
+    }
+
+    /// Parts of the tile where an arm the layout does not have would be, for
+    /// covering up the four-way background: the strip from the tile edge cars
+    /// would come in by to the box, as wide as the box.
+    pub fn closed_arms(&self) -> Vec<Rect> {
+        let outline = self.box_rect();
+        // Along an axis the arm runs from the edge cars come in by to the box;
+        // across it, it spans the box
+        let span = |step: i32, edge: i32, (low, high): (i32, i32)| match step.signum() {
+            1 => (0, low),
+            -1 => (high, edge),
+            _ => (low, high),
+        };
+        Direction::ALL
+            .into_iter()
+            .filter(|&heading| !heading.is_diagonal() && !self.has_approach(heading))
+            .map(|heading| {
+                let (dx, dy) = heading.vector();
+                let (left, right) = span(dx, self.width as i32, (outline.left(), outline.right()));
+                let (top, bottom) = span(dy, self.height as i32, (outline.top(), outline.bottom()));
+                Rect::new(left, top, (right - left).max(0) as u32, (bottom - top).max(0) as u32)
+            })
+            .collect()
+    }
+
+    /// Tile rectangle of a zone cell of the reservation grid. For a ring sector
+    /// it is a lane-wide square around the middle of the sector.
+    pub fn zone_rect(&self, (row, col): ZoneIndex) -> Rect {
+        if let Some(ring) = &self.roundabout {
+            let middle = ring.sector_arc(col, ring.radius as f64, 2).nth(1).unwrap();
+            return Rect::from_center(middle, ring.lane_width, ring.lane_width);
+        }
+        let (x, y) = self.zone_origin;
+        let size = self.zone_size as i32;
+        Rect::new(x + col as i32 * size, y + row as i32 * size, self.zone_size, self.zone_size)
+    }
+
+    /// Closed outline of a zone, for drawing.
+    pub fn zone_outline(&self, zone: ZoneIndex) -> Vec<Point> {
+        let Some(ring) = &self.roundabout else {
+            let rect = self.zone_rect(zone);
+            return vec![rect.top_left(), rect.top_right(), rect.bottom_right(), rect.bottom_left(), rect.top_left()];
+        };
+        let mut outline: Vec<Point> = ring.sector_arc(zone.1, ring.outer_radius(), 8).collect();
+        outline.extend(ring.sector_arc(zone.1, ring.inner_radius(), 8).collect::<Vec<_>>().into_iter().rev());
+        outline.push(outline[0]);
+        outline
+    }
+
+    pub fn zone_at(&self, x: i32, y: i32) -> Option<ZoneIndex> {
+        match &self.roundabout {
+            Some(ring) => ring.sector_at(x, y).map(|sector| (0, sector)),
+            None => self.zones().find(|&zone| self.zone_rect(zone).contains_point((x, y))),
+        }
+    }
+
+    /// Area the zones cover: the zone grid, or the square around the ring.
+    fn zone_bounds(&self) -> Rect {
+        if let Some(ring) = &self.roundabout {
+            let size = 2 * ring.outer_radius().round() as u32;
+            return Rect::from_center(ring.center, size, size);
+        }
+        let (x, y) = self.zone_origin;
+        Rect::new(x, y, self.zone_columns as u32 * self.zone_size, self.zone_rows as u32 * self.zone_size)
+    }
+
+    /// Outline of the intersection box drawn around the zones.
+    pub fn box_rect(&self) -> Rect {
+        let bounds = self.zone_bounds();
+        Rect::new(
+            bounds.x() - self.box_margin,
+            bounds.y() - self.box_margin,
+            bounds.width() + 2 * self.box_margin as u32,
+            bounds.height() + 2 * self.box_margin as u32,
+        )
+    }
+
+    /// Distance from the tile edge a car comes in from to the zones.
+    pub fn entry_distance(&self, heading: Direction) -> i32 {
+        along(self.zone_bounds(), heading).0 - along(self.tile_rect(), heading).0
+    }
+
+    /// How far the front of `body` has come from the tile edge it entered by.
+    pub fn distance_travelled(&self, body: &Body) -> i32 {
+        body.along(body.heading).1 - along(self.tile_rect(), body.heading).0
+    }
+
+    /// How far the front of `body` is short of the entry line, unrounded.
+    pub fn distance_to_entry_line(&self, body: &Body) -> f64 {
+        along(self.zone_bounds(), body.heading).0 as f64 - body.extent_along(body.heading).1
+    }
+
+    pub fn is_past_entry(&self, body: &Body) -> bool {
+        self.distance_travelled(body) >= self.entry_distance(body.heading)
+    }
+
+    /// Whether the rear of `body` is past the tile edge it leaves by.
+    pub fn has_exited(&self, body: &Body) -> bool {
+        body.along(body.heading).0 >= along(self.tile_rect(), body.heading).1
+    }
+
+    /// Body of a car `width` by `length` leaving the ring heading `exit`, its
+    /// rear level with the exit point, in the lane a turn onto `route` ends in
+    /// at a grid.
+    pub fn ring_exit_body(&self, exit: Direction, route: Route, width: u32, length: u32) -> Body {
+        let ring = self.roundabout.as_ref().expect("Layout has no roundabout");
+        self.exit_body(exit, route, dot(ring.exit_point(exit), exit.unit()), width, length)
+    }
+}
+
+fn dot(a: (f64, f64), b: (f64, f64)) -> f64 {
+    a.0 * b.0 + a.1 * b.1
+}
+
+/// Positive when `b` lies clockwise of `a` on screen (y down).
+fn cross(a: (f64, f64), b: (f64, f64)) -> f64 {
+    a.0 * b.1 - a.1 * b.0
+}
+
+/// Intersection in a map file, placed at `position` in network pixels.
+#[derive(Deserialize)]
+pub struct NodeSpec {
+    pub id: String,
+    #[serde(default = "standard_layout_name")]
+    pub layout: String,
+    pub position: (i32, i32),
+}
+
+/// Road segment from one intersection's exit to the next one's approach.
+/// `length_px` is road not drawn on either tile.
+#[derive(Deserialize)]
+pub struct LinkSpec {
+    pub from: String,
+    pub to: String,
+    pub heading: Direction,
+    pub length_px: f64,
+    pub lanes: usize,
+    pub speed_limit_px_per_sec: f64,
+}
+
+fn standard_layout_name() -> String {
+    "standard".to_string()
+}
+
+/// Road network read from a JSON map file: layouts by name, nodes and links.
+/// The `standard` layout is always available and need not be listed.
+#[derive(Deserialize)]
+pub struct RoadMap {
+    #[serde(default)]
+    pub layouts: HashMap<String, Layout>,
+    pub nodes: Vec<NodeSpec>,
+    #[serde(default)]
+    pub links: Vec<LinkSpec>,
+}
+
+impl RoadMap {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let mut map: RoadMap = serde_json::from_str(&text).map_err(|e| e.to_string())?;
+        map.layouts.entry(standard_layout_name()).or_insert_with(Layout::standard);
+        map.validate()?;
+        Ok(map)
+    }
+
+    /// Intersections laid out as a grid of standard tiles, each exit linked
+    /// straight to the neighbouring approach.
+    pub fn grid(rows: usize, columns: usize) -> Self {
+        let layout = Layout::standard();
+        let (width, height) = (layout.width as i32, layout.height as i32);
+        let id = |row: usize, col: usize| format!("({}, {})", row, col);
+
+        let mut nodes = Vec::new();
+        let mut links = Vec::new();
+        for row in 0..rows {
+            for col in 0..columns {
+                nodes.push(NodeSpec { id: id(row, col), layout: standard_layout_name(), position: (col as i32 * width, row as i32 * height) });
+
+                let mut link = |to: String, heading| links.push(LinkSpec {
+                    from: id(row, col),
+                    to,
+                    heading,
+                    length_px: 0.0,
+                    lanes: Lane::ALL.len(),
+                    speed_limit_px_per_sec: SPEED_PX_PER_SEC,
+                });
+                if row > 0 { link(id(row - 1, col), Direction::North) }
+                if row + 1 < rows { link(id(row + 1, col), Direction::South) }
+                if col > 0 { link(id(row, col - 1), Direction::West) }
+                if col + 1 < columns { link(id(row, col + 1), Direction::East) }
+            }
+        }
+
+        RoadMap { layouts: HashMap::from([(standard_layout_name(), layout)]), nodes, links }
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.nodes.is_empty() {
+            return Err("the map has no nodes".to_string());
+        }
+        for (name, layout) in &self.layouts {
+            layout.validate().map_err(|e| format!("layout {}: {}", name, e))?;
+        }
+        for (i, node) in self.nodes.iter().enumerate() {
+            if !self.layouts.contains_key(&node.layout) {
+                return Err(format!("node {} uses unknown layout {}", node.id, node.layout));
+            }
+            if self.nodes[..i].iter().any(|other| other.id == node.id) {
+                return Err(format!("node id {} is used more than once", node.id));
+            }
+        }
+        for (i, link) in self.links.iter().enumerate() {
+            // A node's exit feeds a single link
+            if self.links[..i].iter().any(|other| other.from == link.from && other.heading == link.heading) {
+                return Err(format!("node {} has more than one link heading {:?}", link.from, link.heading));
+            }
+            let layout_of = |id: &String| {
+                let node = self.nodes.iter().find(|node| &node.id == id);
+                node.map(|node| &self.layouts[&node.layout])
+                    .ok_or_else(|| format!("link {} -> {} refers to unknown node {}", link.from, link.to, id))
+            };
+            if !layout_of(&link.from)?.has_approach(link.heading.opposite()) {
+                return Err(format!("node {} has no exit heading {:?}", link.from, link.heading));
+            }
+            if !layout_of(&link.to)?.has_approach(link.heading) {
+                return Err(format!("node {} has no approach heading {:?}", link.to, link.heading));
+            }
+            // Every approach has the curb, middle and median lanes the lane logic assumes
+            if link.lanes != Lane::ALL.len() {
+                return Err(format!("link {} -> {} has {} lanes, only {} are supported", link.from, link.to, link.lanes, Lane::ALL.len()));
+            }
+            if link.length_px < 0.0 || link.speed_limit_px_per_sec <= 0.0 {
+                return Err(format!("link {} -> {} needs a non-negative length and a positive speed limit", link.from, link.to));
+            }
+        }
+        Ok(())
+    }
+}
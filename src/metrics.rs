@@ -0,0 +1,327 @@
+use crate::car::{Car, DriverType, VehicleClass};
+use crate::intersection::{Direction, Lane, Route};
+use crate::map::Layout;
+use crate::pedestrian::Pedestrian;
+use crate::utils::{crossing_speeds, destination_of, lane_routes};
+use crate::consts::*;
+use std::collections::HashMap;
+use std::time::Duration;
+
+pub type Movement = (Direction, Route);
+
+/// Every heading with every route, routes from the sharpest left to the sharpest right.
+pub fn movements() -> impl Iterator<Item = Movement> {
+    Direction::ALL.into_iter().flat_map(|origin| Route::ALL.map(|route| (origin, route)))
+}
+
+/// Traffic-engineering measures for one vehicle that has left the road.
+pub struct DelaySample {
+    pub origin: Direction,
+    pub route: Route,
+    pub driver: DriverType,
+    pub class: VehicleClass,
+    pub travel_time: f64,
+    pub free_flow_time: f64,
+    pub control_delay: f64,
+    /// Time from asking to enter the road to entering the intersection.
+    pub wait: f64,
+    pub stopped_time: f64,
+    pub stops: usize,
+    pub braking_time: f64,
+}
+
+impl DelaySample {
+    pub fn from_car(car: &Car) -> Option<Self> {
+        let exit_time = car.time_exit?;
+        let travel_time = exit_time.duration_since(car.time_enter).ok()?.as_secs_f64();
+        let upstream_wait = car.time_enter.duration_since(car.time_requested).unwrap_or_default().as_secs_f64();
+        let free_flow_time = free_flow_time(car.route, car.dist as f64);
+        let entered = car.actual_entry_time.unwrap_or(exit_time);
+
+        Some(DelaySample {
+            origin: car.origin,
+            route: car.route,
+            driver: car.driver,
+            class: car.class,
+            travel_time,
+            free_flow_time,
+            control_delay: (upstream_wait + travel_time - free_flow_time).max(0.0),
+            wait: entered.duration_since(car.time_requested).unwrap_or_default().as_secs_f64(),
+            stopped_time: car.stopped_time,
+            stops: car.stops,
+            braking_time: car.braking_time,
+        })
+    }
+}
+
+/// Time to drive the route on an empty road: speed up from rest to the
+/// fastest crossing speed on offer, then hold it to the end.
+pub fn free_flow_time(route: Route, distance: f64) -> f64 {
+    let speed = crossing_speeds(route)[0];
+    let ramp_time = speed / IDM_MAX_ACCELERATION;
+    let ramp_distance = speed / 2.0 * ramp_time;
+
+    if ramp_distance >= distance {
+        (2.0 * distance / IDM_MAX_ACCELERATION).sqrt()
+    } else {
+        ramp_time + (distance - ramp_distance) / speed
+    }
+}
+
+/// Nearest-rank percentile, `p` in 0..=100.
+pub fn percentile(values: &[f64], p: f64) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+fn mean(values: impl Iterator<Item = f64>) -> f64 {
+    let (sum, count) = values.fold((0.0, 0), |(sum, count), v| (sum + v, count + 1));
+    if count == 0 { 0.0 } else { sum / count as f64 }
+}
+
+fn report_row(label: &str, samples: &[&DelaySample]) -> String {
+    let delays: Vec<f64> = samples.iter().map(|s| s.control_delay).collect();
+    let travel: Vec<f64> = samples.iter().map(|s| s.travel_time).collect();
+
+    format!(
+        "{:<22}{:>5}{:>8.2}{:>8.2}{:>8.2}{:>8.2}{:>8.2}{:>8.2}{:>9.2}{:>9.2}{:>7.2}{:>9.2}\n",
+        label,
+        samples.len(),
+        percentile(&delays, 50.0),
+        percentile(&delays, 90.0),
+        percentile(&delays, 99.0),
+        percentile(&travel, 50.0),
+        percentile(&travel, 90.0),
+        percentile(&travel, 99.0),
+        mean(samples.iter().map(|s| s.free_flow_time)),
+        mean(samples.iter().map(|s| s.stopped_time)),
+        mean(samples.iter().map(|s| s.stops as f64)),
+        mean(samples.iter().map(|s| s.braking_time)),
+    )
+}
+
+/// Control delay and travel time percentiles per approach and per movement.
+pub fn delay_report(cars: &[Car]) -> String {
+    let samples: Vec<DelaySample> = cars.iter().filter_map(DelaySample::from_car).collect();
+
+    let mut report = format!(
+        "{:<22}{:>5}{:>8}{:>8}{:>8}{:>8}{:>8}{:>8}{:>9}{:>9}{:>7}{:>9}\n",
+        "Group", "n", "dly50", "dly90", "dly99", "tt50", "tt90", "tt99", "freeflow", "stopped", "stops", "braking"
+    );
+    report.push_str(&report_row("All", &samples.iter().collect::<Vec<_>>()));
+
+    // Split by class only when there is more than one
+    if samples.iter().any(|s| s.class != VehicleClass::Regular) {
+        for class in VehicleClass::ALL {
+            let group: Vec<&DelaySample> = samples.iter().filter(|s| s.class == class).collect();
+            if !group.is_empty() {
+                report.push_str(&report_row(&format!("{:?}", class), &group));
+            }
+        }
+    }
+
+    // Split by driver only in mixed traffic
+    if samples.iter().any(|s| s.driver == DriverType::Human) {
+        for driver in [DriverType::Autonomous, DriverType::Human] {
+            let group: Vec<&DelaySample> = samples.iter().filter(|s| s.driver == driver).collect();
+            if !group.is_empty() {
+                report.push_str(&report_row(&format!("{:?}", driver), &group));
+            }
+        }
+    }
+
+    for origin in Direction::ALL {
+        let group: Vec<&DelaySample> = samples.iter().filter(|s| s.origin == origin).collect();
+        if !group.is_empty() {
+            report.push_str(&report_row(&format!("{:?}", origin), &group));
+        }
+    }
+
+    for (origin, route) in movements() {
+        let group: Vec<&DelaySample> = samples
+            .iter()
+            .filter(|s| s.origin == origin && s.route == route)
+            .collect();
+        if !group.is_empty() {
+            report.push_str(&report_row(&format!("{:?} {:?}", origin, route), &group));
+        }
+    }
+
+    report
+}
+
+/// Running demand and queue measures for one movement.
+#[derive(Default)]
+pub struct MovementStats {
+    pub demand: usize,
+    pub max_queue: usize,
+    queue_seconds: f64,
+    observed_seconds: f64,
+}
+
+impl MovementStats {
+    pub fn record_queue(&mut self, length: usize, delta: Duration) {
+        let seconds = delta.as_secs_f64();
+        self.max_queue = self.max_queue.max(length);
+        self.queue_seconds += length as f64 * seconds;
+        self.observed_seconds += seconds;
+    }
+
+    pub fn average_queue(&self) -> f64 {
+        if self.observed_seconds > 0.0 {
+            self.queue_seconds / self.observed_seconds
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Uncontested capacity (veh/h) of a movement: the lanes serving it, each
+/// releasing one car per zone window (or car-following headway for right turns
+/// that bypass the zones). All lanes of a roundabout arm merge into the one ring.
+pub fn movement_capacity(route: Route, layout: &Layout) -> f64 {
+    let speed = crossing_speeds(route)[0];
+    let headway = if layout.bypasses_zones(route) {
+        (CAR_HEIGHT_PX as f64 + BRAKE_DISTANCE_PX as f64) / speed + IDM_TIME_HEADWAY
+    } else {
+        (layout.zone_length() + CAR_HEIGHT_PX as f64 + SAFE_DISTANCE_PX) / speed
+    };
+    let lanes = match layout.roundabout {
+        Some(_) => 1,
+        None => Lane::ALL.iter().filter(|&&lane| lane_routes(lane).contains(&route.side())).count(),
+    };
+
+    lanes as f64 * 3600.0 / headway
+}
+
+/// HCM level of service from average control delay (s/veh); over capacity is always F.
+pub fn level_of_service(control_delay: f64, vc_ratio: f64) -> char {
+    if vc_ratio > 1.0 {
+        return 'F';
+    }
+    match control_delay {
+        d if d <= 10.0 => 'A',
+        d if d <= 20.0 => 'B',
+        d if d <= 35.0 => 'C',
+        d if d <= 55.0 => 'D',
+        d if d <= 80.0 => 'E',
+        _ => 'F',
+    }
+}
+
+/// One row per movement the layout allows: vehicles served and served per hour,
+/// volume-to-capacity ratio, average control delay, the longest any car waited
+/// to get in, queue lengths and level of service over `elapsed` seconds. The
+/// longest wait also counts the cars in `waiting`, the wait so far of those yet
+/// to get in, so a movement starved right now shows up before any of it is served.
+pub fn movement_report(
+    cars: &[Car],
+    waiting: &HashMap<Movement, f64>,
+    stats: &HashMap<Movement, MovementStats>,
+    elapsed: f64,
+    layout: &Layout,
+) -> String {
+    let hours = (elapsed / 3600.0).max(f64::EPSILON);
+    let samples: Vec<DelaySample> = cars.iter().filter_map(DelaySample::from_car).collect();
+
+    let mut report = format!(
+        "{:<22}{:>8}{:>8}{:>8}{:>7}{:>9}{:>9}{:>8}{:>8}{:>5}\n",
+        "Movement", "served", "veh/h", "cap", "v/c", "delay", "max wait", "avg q", "max q", "LOS"
+    );
+
+    for movement in movements() {
+        let (origin, route) = movement;
+        if !layout.allows(origin, destination_of(origin, route)) {
+            continue;
+        }
+        let movement_stats = &stats[&movement];
+        let group: Vec<&DelaySample> = samples.iter().filter(|s| s.origin == origin && s.route == route).collect();
+        let delays: Vec<f64> = group.iter().map(|s| s.control_delay).collect();
+
+        let capacity = movement_capacity(route, layout);
+        let vc_ratio = movement_stats.demand as f64 / hours / capacity;
+        let avg_delay = mean(delays.iter().copied());
+
+        report.push_str(&format!(
+            "{:<22}{:>8}{:>8.0}{:>8.0}{:>7.2}{:>9.2}{:>9.2}{:>8.2}{:>8}{:>5}\n",
+            format!("{:?} {:?}", origin, route),
+            delays.len(),
+            delays.len() as f64 / hours,
+            capacity,
+            vc_ratio,
+            avg_delay,
+            group.iter().map(|s| s.wait).fold(waiting.get(&movement).copied().unwrap_or(0.0), f64::max),
+            movement_stats.average_queue(),
+            movement_stats.max_queue,
+            level_of_service(avg_delay, vc_ratio),
+        ));
+    }
+
+    report
+}
+
+/// One row per crosswalk: pedestrians across, their average and longest wait at
+/// the kerb, and how many of them a car ran into.
+pub fn pedestrian_report(pedestrians: &[Pedestrian]) -> String {
+    let mut report = format!("{:<16}{:>8}{:>9}{:>9}{:>10}\n", "Crosswalk", "crossed", "avg wait", "max wait", "conflicts");
+
+    for approach in Direction::ALL {
+        let group: Vec<&Pedestrian> = pedestrians.iter().filter(|p| p.approach == approach).collect();
+        if group.is_empty() {
+            continue;
+        }
+        let waits: Vec<f64> = group.iter().map(|p| p.waited().as_secs_f64()).collect();
+        report.push_str(&format!(
+            "{:<16}{:>8}{:>9.2}{:>9.2}{:>10}\n",
+            format!("{:?}", approach),
+            group.len(),
+            mean(waits.iter().copied()),
+            waits.iter().copied().fold(0.0, f64::max),
+            group.iter().filter(|p| p.conflict).count(),
+        ));
+    }
+
+    report
+}
+
+/// One row per emergency vehicle: its control delay, travel time and stops.
+/// A last line counts the cars pushed aside for them, or for any vehicle with a
+/// stronger claim, and how much later than first booked they got in.
+pub fn emergency_report(cars: &[Car]) -> String {
+    let mut report = format!("{:<16}{:>22}{:>8}{:>8}{:>7}\n", "Emergency", "movement", "delay", "travel", "stops");
+
+    for car in cars.iter().filter(|car| car.class == VehicleClass::Emergency) {
+        let Some(sample) = DelaySample::from_car(car) else {
+            continue;
+        };
+        report.push_str(&format!(
+            "{:<16}{:>22}{:>8.2}{:>8.2}{:>7}\n",
+            car.id,
+            format!("{:?} {:?}", sample.origin, sample.route),
+            sample.control_delay,
+            sample.travel_time,
+            sample.stops,
+        ));
+    }
+
+    let added: Vec<f64> = cars
+        .iter()
+        .filter_map(|car| {
+            let entered = car.actual_entry_time?;
+            Some(entered.duration_since(car.displaced_from?).unwrap_or_default().as_secs_f64())
+        })
+        .collect();
+    report.push_str(&format!(
+        "Cars displaced: {}, added entry delay avg/max: {:.2} / {:.2} s\n",
+        added.len(),
+        mean(added.iter().copied()),
+        added.iter().copied().fold(0.0, f64::max),
+    ));
+
+    report
+}
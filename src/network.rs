@@ -0,0 +1,287 @@
+use crate::car::{DriverType, VehicleClass};
+use crate::consts::*;
+use crate::demand::OdMatrix;
+use crate::intersection::{Direction, Intersection, Route};
+use crate::map::RoadMap;
+use crate::spawn_queue::QueuedCar;
+use rand::prelude::*;
+use rand::rng;
+use sdl2::rect::Rect;
+use sdl2::render::Texture;
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+
+/// Road from one intersection's exit to the next one's approach.
+/// Cars take `length_px / speed_limit` seconds over the part not drawn on either tile.
+pub struct Link {
+    pub to: usize,
+    pub length_px: f64,
+    pub speed_limit: f64,
+}
+
+impl Link {
+    pub fn transit_time(&self) -> Duration {
+        Duration::from_secs_f64(self.length_px / self.speed_limit)
+    }
+}
+
+/// Intersections placed and linked as described by a road map.
+/// Every intersection keeps its own lanes, `CrossingManager` and statistics;
+/// cars are handed on through the next intersection's upstream queue.
+pub struct Network<'a> {
+    pub nodes: Vec<Intersection<'a>>,
+    /// Node ids from the map.
+    pub names: Vec<String>,
+    /// Top-left corner of each node's tile in network coordinates.
+    pub origins: Vec<(i32, i32)>,
+    /// Outgoing road by node and exit heading.
+    pub links: HashMap<(usize, Direction), Link>,
+    /// Demand for cars entering the network; each node keeps its own copy
+    /// without the movements its layout lacks.
+    pub od_matrix: OdMatrix,
+    /// Intersection the viewer, keyboard and inspector act on.
+    pub focus: usize,
+    /// Book each car's slot at the next intersection as it enters the current one,
+    /// so platoons can run a green wave instead of booking on arrival.
+    pub prebook: bool,
+    /// Share of new cars that are autonomous; the rest have human drivers.
+    pub av_share: f64,
+    /// Shares of new cars that are buses and freight trucks; the rest are regular cars.
+    pub bus_share: f64,
+    pub freight_share: f64,
+    /// Cars on a link, with when and where they join the next upstream queue.
+    in_transit: Vec<(SystemTime, usize, Direction, QueuedCar)>,
+}
+
+impl<'a> Network<'a> {
+    pub fn new(
+        map: &'a RoadMap,
+        car_textures: HashMap<Route, &'a Texture<'a>>,
+        od_matrix: OdMatrix,
+        queue_capacity: Option<usize>,
+    ) -> Self {
+        let started_at = SystemTime::now();
+        let several = map.nodes.len() > 1;
+        let nodes = map
+            .nodes
+            .iter()
+            .enumerate()
+            .map(|(i, node)| {
+                let layout = &map.layouts[&node.layout];
+                let mut intersection = Intersection::new(layout, car_textures.clone(), od_matrix.clone(), queue_capacity, started_at);
+                intersection.id_generator.node = several.then_some(i);
+                intersection
+            })
+            .collect();
+
+        let index = |id: &str| map.nodes.iter().position(|node| node.id == id).unwrap();
+        let links = map
+            .links
+            .iter()
+            .map(|link| {
+                let to = index(&link.to);
+                ((index(&link.from), link.heading), Link { to, length_px: link.length_px, speed_limit: link.speed_limit_px_per_sec })
+            })
+            .collect();
+
+        let mut network = Network {
+            nodes,
+            names: map.nodes.iter().map(|node| node.id.clone()).collect(),
+            origins: map.nodes.iter().map(|node| node.position).collect(),
+            links,
+            od_matrix: od_matrix.clone(),
+            focus: 0,
+            prebook: true,
+            av_share: 1.0,
+            bus_share: 0.0,
+            freight_share: 0.0,
+            in_transit: Vec::new(),
+        };
+        // Cars drawn for a heading no node lets in, e.g. a diagonal one on a map
+        // without diagonal arms, would never appear
+        network.od_matrix = od_matrix.restricted(|origin, _| !network.entry_nodes(origin).is_empty());
+        network
+    }
+
+    pub fn focused(&self) -> &Intersection<'a> {
+        &self.nodes[self.focus]
+    }
+
+    /// Top-left corner of a node's tile in network coordinates.
+    pub fn origin(&self, node: usize) -> (i32, i32) {
+        self.origins[node]
+    }
+
+    /// Tile of a node in network coordinates.
+    pub fn tile(&self, node: usize) -> Rect {
+        let (x, y) = self.origins[node];
+        let layout = self.nodes[node].layout;
+        Rect::new(x, y, layout.width, layout.height)
+    }
+
+    /// Extent of the network from (0, 0) to the far corner of the outermost tile.
+    pub fn size(&self) -> (u32, u32) {
+        (0..self.nodes.len()).fold((0, 0), |(width, height), node| {
+            let tile = self.tile(node);
+            (width.max(tile.right().max(0) as u32), height.max(tile.bottom().max(0) as u32))
+        })
+    }
+
+    /// Node whose tile contains the network point (x, y).
+    pub fn node_at(&self, x: i32, y: i32) -> Option<usize> {
+        (0..self.nodes.len()).find(|&node| self.tile(node).contains_point((x, y)))
+    }
+
+    /// Node reached by leaving `node` heading `heading`, if a link leads anywhere.
+    pub fn neighbor(&self, node: usize, heading: Direction) -> Option<usize> {
+        self.links.get(&(node, heading)).map(|link| link.to)
+    }
+
+    /// Nodes with an approach heading `heading` that no link feeds, where cars enter the network.
+    fn entry_nodes(&self, heading: Direction) -> Vec<usize> {
+        (0..self.nodes.len())
+            .filter(|&node| self.nodes[node].layout.has_approach(heading))
+            .filter(|&node| !self.links.iter().any(|(&(_, h), link)| h == heading && link.to == node))
+            .collect()
+    }
+
+    /// Exit heading at every intersection from `node` until the car leaves the network.
+    /// Turns are drawn from each intersection's OD matrix; after `MAX_PATH_TURNS`
+    /// intersections the car goes straight on so that every path ends at the edge.
+    fn plan_path(&self, mut node: usize, mut heading: Direction) -> Option<Vec<Direction>> {
+        let mut path = Vec::new();
+
+        loop {
+            let exit = if path.len() < MAX_PATH_TURNS {
+                self.nodes[node].od_matrix.sample_destination(heading)?
+            } else {
+                heading
+            };
+            path.push(exit);
+            // Straight roads may loop back on themselves in a map
+            if path.len() >= MAX_PATH_TURNS + self.nodes.len() {
+                return Some(path);
+            }
+
+            match self.neighbor(node, exit) {
+                Some(next) => (node, heading) = (next, exit),
+                None => return Some(path),
+            }
+        }
+    }
+
+    fn request_path(&mut self, node: usize, heading: Direction, class: VehicleClass) {
+        let Some(path) = self.plan_path(node, heading) else {
+            return;
+        };
+        // Emergency vehicles always book their way through
+        let driver = if class == VehicleClass::Emergency || rng().random_bool(self.av_share) {
+            DriverType::Autonomous
+        } else {
+            DriverType::Human
+        };
+        let intersection = &mut self.nodes[node];
+        let car = QueuedCar {
+            destination: path[0],
+            requested_at: intersection.now,
+            onward: path[1..].to_vec(),
+            id: None,
+            speed: None,
+            driver,
+            class,
+        };
+        intersection.request_car(heading, car);
+    }
+
+    /// New car heading `origin` into the focused intersection.
+    pub fn add_car_in(&mut self, origin: Direction) {
+        if !self.focused().layout.has_approach(origin) {
+            println!("Intersection {} has no approach heading {:?}", self.names[self.focus], origin);
+            return;
+        }
+        let class = self.draw_class();
+        self.request_path(self.focus, origin, class);
+    }
+
+    /// New car entering the network from its edge, heading drawn from the OD matrix.
+    pub fn add_car_in_rnd(&mut self) {
+        self.add_class_in_rnd(self.draw_class());
+    }
+
+    /// New emergency vehicle entering the network from its edge.
+    pub fn add_emergency_vehicle(&mut self) {
+        self.add_class_in_rnd(VehicleClass::Emergency);
+    }
+
+    fn draw_class(&self) -> VehicleClass {
+        let draw: f64 = rng().random();
+        if draw < self.bus_share {
+            VehicleClass::Bus
+        } else if draw < self.bus_share + self.freight_share {
+            VehicleClass::Freight
+        } else {
+            VehicleClass::Regular
+        }
+    }
+
+    fn add_class_in_rnd(&mut self, class: VehicleClass) {
+        let Some((heading, _)) = self.od_matrix.sample() else {
+            return;
+        };
+        if let Some(&node) = self.entry_nodes(heading).choose(&mut rng()) {
+            self.request_path(node, heading, class);
+        }
+    }
+
+    /// New pedestrian at a crosswalk of every intersection that has any.
+    pub fn add_pedestrians(&mut self) {
+        for node in self.nodes.iter_mut() {
+            node.add_pedestrian();
+        }
+    }
+
+    /// Advance every intersection, pass arrival forecasts downstream, then hand
+    /// cars that left one on to the next, by way of the link between them.
+    pub fn update(&mut self, delta: Duration) {
+        for node in self.nodes.iter_mut() {
+            node.update(delta);
+        }
+        let now = self.nodes[0].now;
+
+        for node in 0..self.nodes.len() {
+            for mut forecast in std::mem::take(&mut self.nodes[node].forecasts) {
+                if let Some(link) = self.links.get(&(node, forecast.heading)).filter(|_| self.prebook) {
+                    forecast.at += link.transit_time();
+                    forecast.speed = forecast.speed.min(link.speed_limit);
+                    self.nodes[link.to].prebook(&forecast);
+                }
+            }
+
+            for (heading, mut car) in std::mem::take(&mut self.nodes[node].outbound) {
+                match self.links.get(&(node, heading)) {
+                    Some(link) => {
+                        car.speed = car.speed.map(|speed| speed.min(link.speed_limit));
+                        self.in_transit.push((now + link.transit_time(), link.to, heading, car));
+                    }
+                    None => println!("Car {:?} left the network with path remaining", car.id),
+                }
+            }
+        }
+
+        let (arrived, in_transit) = std::mem::take(&mut self.in_transit).into_iter().partition(|(at, ..)| *at <= now);
+        self.in_transit = in_transit;
+        for (_, next, heading, car) in arrived {
+            self.nodes[next].request_car(heading, car);
+        }
+    }
+
+    /// Simulated time since the network opened.
+    pub fn elapsed(&self) -> Duration {
+        self.nodes[0].elapsed()
+    }
+
+    /// Vehicles that have left any intersection, counting through traffic once per intersection.
+    pub fn crossed(&self) -> usize {
+        self.nodes.iter().map(|node| node.cars_out.len()).sum()
+    }
+}
@@ -0,0 +1,117 @@
+use crate::crossing_manager::ZoneIndex;
+use crate::intersection::Direction;
+use crate::map::{Crosswalk, Layout};
+use sdl2::rect::Rect;
+use sdl2::render::{Canvas, RenderTarget};
+use std::time::{Duration, SystemTime};
+
+use crate::consts::*;
+
+/// Someone crossing an arm on foot. They wait off the kerb until the walk the
+/// crossing manager booked for them starts, then cross at a steady pace.
+pub struct Pedestrian {
+    pub id: String,
+    /// Arm being crossed, named by the heading of the cars arriving on it.
+    pub approach: Direction,
+    /// Crosswalk cells in the order they are walked.
+    pub cells: Vec<ZoneIndex>,
+    pub x: f64,
+    pub y: f64,
+    /// Unit step along the crosswalk.
+    step: (f64, f64),
+    /// Distance from the kerb until the whole body has left the last cell.
+    distance: f64,
+    walked: f64,
+    pub arrived_at: SystemTime,
+    pub start_at: SystemTime,
+    pub finished_at: Option<SystemTime>,
+    /// A car touched this pedestrian on the way across.
+    pub conflict: bool,
+}
+
+impl Pedestrian {
+    /// Pedestrian arriving at one kerb of `crosswalk`, or at the far one if
+    /// `reverse`. They step off at `start_at`, which is `now` until a walk is booked.
+    pub fn new(id: String, layout: &Layout, crosswalk: &Crosswalk, reverse: bool, now: SystemTime) -> Self {
+        let mut cells = crosswalk.zones.clone();
+        if reverse {
+            cells.reverse();
+        }
+        let first = layout.zone_rect(cells[0]);
+        let last = layout.zone_rect(*cells.last().unwrap());
+        let size = PEDESTRIAN_SIZE_PX as f64;
+
+        // Arms running north-south are crossed along x, the others along y
+        let across_x = crosswalk.approach.is_vertical();
+        let (from, to, span) = if across_x {
+            (first.center().x(), last.center().x(), first.width())
+        } else {
+            (first.center().y(), last.center().y(), first.height())
+        };
+        let sign = if to < from || (to == from && reverse) { -1.0 } else { 1.0 };
+        let kerb = from as f64 - sign * span as f64 / 2.0;
+        let start = if sign > 0.0 { kerb - size } else { kerb };
+        let middle = if across_x { first.center().y() } else { first.center().x() } as f64 - size / 2.0;
+
+        let (x, y, step) = if across_x { (start, middle, (sign, 0.0)) } else { (middle, start, (0.0, sign)) };
+        Pedestrian {
+            id,
+            approach: crosswalk.approach,
+            cells,
+            x,
+            y,
+            step,
+            distance: (to - from).abs() as f64 + span as f64 + size,
+            walked: 0.0,
+            arrived_at: now,
+            start_at: now,
+            finished_at: None,
+            conflict: false,
+        }
+    }
+
+    pub fn bounding_box(&self) -> Rect {
+        Rect::new(self.x.round() as i32, self.y.round() as i32, PEDESTRIAN_SIZE_PX, PEDESTRIAN_SIZE_PX)
+    }
+
+    pub fn is_walking(&self, now: SystemTime) -> bool {
+        self.finished_at.is_none() && self.start_at <= now
+    }
+
+    /// Cells the pedestrian is on or has yet to reach.
+    pub fn cells_ahead(&self, layout: &Layout) -> &[ZoneIndex] {
+        let bb = self.bounding_box();
+        match self.cells.iter().position(|&cell| layout.zone_rect(cell).has_intersection(bb)) {
+            Some(i) => &self.cells[i..],
+            None if self.walked == 0.0 => &self.cells,
+            None => &[],
+        }
+    }
+
+    /// Time spent at the kerb before stepping off.
+    pub fn waited(&self) -> Duration {
+        self.start_at.duration_since(self.arrived_at).unwrap_or_default()
+    }
+
+    /// Walk on for `delta`, ending at simulation time `now`.
+    pub fn update(&mut self, now: SystemTime, delta: Duration) {
+        if !self.is_walking(now) {
+            return;
+        }
+        // Step off on time even when the booked start falls between ticks
+        let seconds = delta.as_secs_f64().min(now.duration_since(self.start_at).unwrap_or_default().as_secs_f64());
+        let travel = (PEDESTRIAN_SPEED_PX_PER_SEC * seconds).min(self.distance - self.walked);
+        self.walked += travel;
+        self.x += self.step.0 * travel;
+        self.y += self.step.1 * travel;
+
+        if self.walked >= self.distance {
+            self.finished_at = Some(now);
+        }
+    }
+
+    pub fn draw<T: RenderTarget>(&self, canvas: &mut Canvas<T>) {
+        canvas.set_draw_color(if self.conflict { PEDESTRIAN_CONFLICT_COLOR } else { PEDESTRIAN_COLOR });
+        canvas.fill_rect(self.bounding_box()).unwrap();
+    }
+}
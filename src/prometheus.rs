@@ -0,0 +1,118 @@
+use crate::intersection::{Direction, Intersection};
+use crate::network::Network;
+use std::fmt::Write as _;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// Serves the latest metrics snapshot in Prometheus text format over HTTP.
+/// The simulation thread publishes a fresh snapshot every frame; the server
+/// thread only ever reads the last one, so scrapes never touch live state.
+pub struct MetricsServer {
+    snapshot: Arc<Mutex<String>>,
+}
+
+impl MetricsServer {
+    pub fn start(port: u16) -> io::Result<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        let snapshot = Arc::new(Mutex::new(String::new()));
+        let shared = Arc::clone(&snapshot);
+
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                if let Err(e) = respond(stream, &shared) {
+                    println!("Metrics request failed: {}", e);
+                }
+            }
+        });
+
+        println!("Serving metrics on http://127.0.0.1:{}/metrics", port);
+        Ok(MetricsServer { snapshot })
+    }
+
+    pub fn publish(&self, network: &Network, frame_time: Duration) {
+        let text = render(network, frame_time);
+        if let Ok(mut snapshot) = self.snapshot.lock() {
+            *snapshot = text;
+        }
+    }
+}
+
+fn respond(stream: TcpStream, snapshot: &Mutex<String>) -> io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(2)))?;
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+
+    // Drain the headers; the request has no body we care about
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    let path = request_line.split_whitespace().nth(1).unwrap_or("");
+    let (status, body) = if path == "/metrics" {
+        ("200 OK", snapshot.lock().map(|s| s.clone()).unwrap_or_default())
+    } else {
+        ("404 Not Found", "Not found\n".to_string())
+    };
+
+    let mut stream = reader.into_inner();
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )
+}
+
+fn metric(out: &mut String, name: &str, kind: &str, help: &str, value: impl std::fmt::Display) {
+    let _ = writeln!(out, "# HELP {} {}\n# TYPE {} {}\n{} {}", name, help, name, kind, name, value);
+}
+
+/// Counters and gauges summed over every intersection of the network.
+fn render(network: &Network, frame_time: Duration) -> String {
+    let mut out = String::new();
+    let nodes = &network.nodes;
+    let sum = |f: &dyn Fn(&Intersection) -> usize| nodes.iter().map(f).sum::<usize>();
+
+    let zones_occupied = sum(&|node| node.crossing_manager.zone_counts(node.now).1);
+    let dropped = sum(&|node| node.spawn_queues.values().map(|queue| queue.dropped).sum());
+
+    metric(&mut out, "smart_road_collisions_total", "counter", "Collisions detected.", sum(&|node| node.collision_count));
+    metric(&mut out, "smart_road_near_misses_total", "counter", "Times a car had to brake hard.", sum(&|node| node.near_miss));
+    metric(&mut out, "smart_road_vehicles_crossed_total", "counter", "Vehicles that left the road.", network.crossed());
+    metric(&mut out, "smart_road_dropped_demand_total", "counter", "Vehicles turned away by a full upstream queue.", dropped);
+    metric(
+        &mut out,
+        "smart_road_active_reservations",
+        "gauge",
+        "Zone reservations currently booked.",
+        sum(&|node| node.crossing_manager.reservation_count()),
+    );
+    metric(&mut out, "smart_road_zones_occupied", "gauge", "Zones held at this moment.", zones_occupied);
+    metric(
+        &mut out,
+        "smart_road_vehicles_on_road",
+        "gauge",
+        "Vehicles between spawn and exit.",
+        sum(&|node| node.cars_in.values().map(Vec::len).sum()),
+    );
+    metric(&mut out, "smart_road_frame_time_seconds", "gauge", "Time spent on the last frame.", frame_time.as_secs_f64());
+
+    let _ = writeln!(out, "# HELP smart_road_queue_length Vehicles waiting upstream of an approach.");
+    let _ = writeln!(out, "# TYPE smart_road_queue_length gauge");
+    for dir in Direction::ALL.into_iter().filter(|&dir| nodes.iter().any(|node| node.layout.has_approach(dir))) {
+        let _ = writeln!(
+            out,
+            "smart_road_queue_length{{direction=\"{}\"}} {}",
+            format!("{:?}", dir).to_lowercase(),
+            sum(&|node| node.spawn_queues[&dir].waiting.len())
+        );
+    }
+
+    out
+}
@@ -0,0 +1,75 @@
+use crate::car::{DriverType, VehicleClass};
+use crate::intersection::Direction;
+use std::collections::VecDeque;
+use std::time::{Duration, SystemTime};
+
+/// Car waiting upstream of an approach for room to enter.
+#[derive(Clone)]
+pub struct QueuedCar {
+    pub destination: Direction,
+    pub requested_at: SystemTime,
+    /// Exit headings at the intersections after this one, for cars crossing a network.
+    pub onward: Vec<Direction>,
+    /// Id the car already has from an earlier intersection; given on queueing otherwise.
+    pub id: Option<String>,
+    /// Speed the car carries over from an earlier intersection, instead of the spawn speed.
+    pub speed: Option<f64>,
+    pub driver: DriverType,
+    pub class: VehicleClass,
+}
+
+/// Virtual queue of demand that could not enter its approach yet.
+/// `capacity` of `None` means the queue never turns demand away.
+pub struct SpawnQueue {
+    pub waiting: VecDeque<QueuedCar>,
+    pub capacity: Option<usize>,
+    pub max_length: usize,
+    pub dropped: usize,
+    length_seconds: f64,
+    observed_seconds: f64,
+}
+
+impl SpawnQueue {
+    pub fn new(capacity: Option<usize>) -> Self {
+        SpawnQueue {
+            waiting: VecDeque::new(),
+            capacity,
+            max_length: 0,
+            dropped: 0,
+            length_seconds: 0.0,
+            observed_seconds: 0.0,
+        }
+    }
+
+    /// Add a car to the back of the queue, or an emergency vehicle ahead of every
+    /// regular car; returns `false` if the queue is full. Emergency vehicles are
+    /// never turned away.
+    pub fn push(&mut self, car: QueuedCar) -> bool {
+        if car.class == VehicleClass::Emergency {
+            let at = self.waiting.iter().take_while(|queued| queued.class == VehicleClass::Emergency).count();
+            self.waiting.insert(at, car);
+        } else if self.capacity.is_some_and(|cap| self.waiting.len() >= cap) {
+            self.dropped += 1;
+            return false;
+        } else {
+            self.waiting.push_back(car);
+        }
+        self.max_length = self.max_length.max(self.waiting.len());
+        true
+    }
+
+    /// Accumulate the queue length over `delta` for the time-averaged length.
+    pub fn record(&mut self, delta: Duration) {
+        let seconds = delta.as_secs_f64();
+        self.length_seconds += self.waiting.len() as f64 * seconds;
+        self.observed_seconds += seconds;
+    }
+
+    pub fn average_length(&self) -> f64 {
+        if self.observed_seconds > 0.0 {
+            self.length_seconds / self.observed_seconds
+        } else {
+            0.0
+        }
+    }
+}
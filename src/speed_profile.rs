@@ -0,0 +1,139 @@
+use crate::consts::*;
+
+/// Accelerate/cruise/decelerate plan that covers a distance in a given time.
+/// Phase one ramps from `start_speed` to `cruise_speed`, phase two holds it,
+/// phase three ramps to `entry_speed` right at the end of the distance.
+#[derive(Debug, Clone, Copy)]
+pub struct SpeedProfile {
+    pub start_speed: f64,
+    pub cruise_speed: f64,
+    pub entry_speed: f64,
+    pub ramp_in_time: f64,
+    pub cruise_time: f64,
+    pub ramp_out_time: f64,
+}
+
+impl SpeedProfile {
+    /// Cruise at the entry speed, used when there is nothing left to plan.
+    pub fn constant(speed: f64) -> Self {
+        SpeedProfile {
+            start_speed: speed,
+            cruise_speed: speed,
+            entry_speed: speed,
+            ramp_in_time: 0.0,
+            cruise_time: 0.0,
+            ramp_out_time: 0.0,
+        }
+    }
+
+    pub fn duration(&self) -> f64 {
+        self.ramp_in_time + self.cruise_time + self.ramp_out_time
+    }
+
+    pub fn speed_at(&self, t: f64) -> f64 {
+        let cruise_end = self.ramp_in_time + self.cruise_time;
+
+        if t < self.ramp_in_time {
+            self.start_speed + (self.cruise_speed - self.start_speed) * t / self.ramp_in_time
+        } else if t < cruise_end {
+            self.cruise_speed
+        } else if t < self.duration() {
+            self.cruise_speed
+                + (self.entry_speed - self.cruise_speed) * (t - cruise_end) / self.ramp_out_time
+        } else {
+            self.entry_speed
+        }
+    }
+
+    /// Distance covered after `t` seconds of following the profile.
+    pub fn distance_at(&self, t: f64) -> f64 {
+        let ramp_in = t.clamp(0.0, self.ramp_in_time);
+        let cruise = (t - self.ramp_in_time).clamp(0.0, self.cruise_time);
+        let ramp_out_start = self.ramp_in_time + self.cruise_time;
+        let ramp_out = (t - ramp_out_start).clamp(0.0, self.ramp_out_time);
+        let after = (t - self.duration()).max(0.0);
+
+        (self.start_speed + self.speed_at(ramp_in)) / 2.0 * ramp_in
+            + self.cruise_speed * cruise
+            + (self.cruise_speed + self.speed_at(ramp_out_start + ramp_out)) / 2.0 * ramp_out
+            + self.entry_speed * after
+    }
+}
+
+/// Time and distance to change speed at the car's acceleration limits.
+fn ramp(from: f64, to: f64) -> (f64, f64) {
+    let rate = if to > from { IDM_MAX_ACCELERATION } else { IDM_COMFORT_DECELERATION };
+    let time = (to - from).abs() / rate;
+    (time, (from + to) / 2.0 * time)
+}
+
+fn profile_through(start_speed: f64, cruise_speed: f64, entry_speed: f64, distance: f64) -> Option<SpeedProfile> {
+    let (ramp_in_time, ramp_in_dist) = ramp(start_speed, cruise_speed);
+    let (ramp_out_time, ramp_out_dist) = ramp(cruise_speed, entry_speed);
+    let cruise_dist = distance - ramp_in_dist - ramp_out_dist;
+
+    if cruise_speed <= 0.0 || cruise_dist < 0.0 {
+        return None;
+    }
+
+    Some(SpeedProfile {
+        start_speed,
+        cruise_speed,
+        entry_speed,
+        ramp_in_time,
+        cruise_time: cruise_dist / cruise_speed,
+        ramp_out_time,
+    })
+}
+
+fn candidate_profiles(start_speed: f64, distance: f64, entry_speed: f64) -> impl Iterator<Item = SpeedProfile> {
+    (1..=PROFILE_SEARCH_STEPS).filter_map(move |step| {
+        let cruise_speed = SPEED_PX_PER_SEC * step as f64 / PROFILE_SEARCH_STEPS as f64;
+        profile_through(start_speed, cruise_speed, entry_speed, distance)
+    })
+}
+
+/// Quickest way to cover `distance` and reach the intersection at `entry_speed`.
+pub fn earliest_arrival(start_speed: f64, distance: f64, entry_speed: f64) -> Option<SpeedProfile> {
+    candidate_profiles(start_speed, distance, entry_speed)
+        .min_by(|a, b| a.duration().total_cmp(&b.duration()))
+}
+
+/// Plan a profile that reaches the entry after exactly `time_left` seconds.
+/// Returns `None` when the slot cannot be met within the acceleration limits.
+pub fn plan_arrival(start_speed: f64, distance: f64, time_left: f64, entry_speed: f64) -> Option<SpeedProfile> {
+    let mut previous: Option<SpeedProfile> = None;
+
+    for profile in candidate_profiles(start_speed, distance, entry_speed) {
+        if let Some(prev) = previous
+            && (prev.duration() - time_left) * (profile.duration() - time_left) <= 0.0
+        {
+            return Some(refine(prev, profile, distance, time_left));
+        }
+        previous = Some(profile);
+    }
+
+    // No bracket (e.g. a single cruise speed fits): accept the closest within a frame
+    candidate_profiles(start_speed, distance, entry_speed)
+        .min_by(|a, b| (a.duration() - time_left).abs().total_cmp(&(b.duration() - time_left).abs()))
+        .filter(|p| (p.duration() - time_left).abs() <= BASE_DELTA_TIME.as_secs_f64())
+}
+
+/// Bisect the cruise speed between two profiles bracketing `time_left`.
+fn refine(slow: SpeedProfile, fast: SpeedProfile, distance: f64, time_left: f64) -> SpeedProfile {
+    let (mut lo, mut hi) = (slow, fast);
+
+    for _ in 0..PROFILE_REFINE_ITERATIONS {
+        let mid_speed = (lo.cruise_speed + hi.cruise_speed) / 2.0;
+        let Some(mid) = profile_through(lo.start_speed, mid_speed, lo.entry_speed, distance) else {
+            break;
+        };
+        if (lo.duration() - time_left) * (mid.duration() - time_left) <= 0.0 {
+            hi = mid;
+        } else {
+            lo = mid;
+        }
+    }
+
+    if (lo.duration() - time_left).abs() < (hi.duration() - time_left).abs() { lo } else { hi }
+}
@@ -0,0 +1,92 @@
+use crate::intersection::{Direction, Lane};
+use crate::network::Network;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::time::Duration;
+
+/// Samples aggregate simulation state at a fixed interval of simulated time into CSV columns.
+/// Every column is summed over the intersections of the network. Headings get
+/// columns only if some intersection has an approach for them, so the header is
+/// written with the first row.
+pub struct TimeSeriesWriter {
+    out: BufWriter<File>,
+    interval: Duration,
+    next_sample: Duration,
+    header_written: bool,
+}
+
+/// Headings some intersection of the network has an approach for.
+fn headings(network: &Network) -> Vec<Direction> {
+    Direction::ALL
+        .into_iter()
+        .filter(|&dir| network.nodes.iter().any(|node| node.layout.has_approach(dir)))
+        .collect()
+}
+
+impl TimeSeriesWriter {
+    pub fn create(path: &str, interval: Duration) -> io::Result<Self> {
+        let out = BufWriter::new(File::create(path)?);
+        let interval = interval.max(Duration::from_millis(1));
+        Ok(TimeSeriesWriter { out, interval, next_sample: Duration::ZERO, header_written: false })
+    }
+
+    fn write_header(&mut self, headings: &[Direction]) -> io::Result<()> {
+        let mut header = vec!["time_s".to_string()];
+        for dir in headings {
+            for lane in Lane::ALL {
+                header.push(format!("cars_{:?}_{:?}", dir, lane).to_lowercase());
+            }
+        }
+        for dir in headings {
+            header.push(format!("queued_{:?}", dir).to_lowercase());
+        }
+        header.extend(
+            ["in_intersection", "zones_reserved", "zones_occupied", "crossed", "braking"].map(String::from),
+        );
+        writeln!(self.out, "{}", header.join(","))?;
+        self.header_written = true;
+        Ok(())
+    }
+
+    /// Write a row if the sampling interval has elapsed since the last one.
+    pub fn sample(&mut self, network: &Network) -> io::Result<()> {
+        let elapsed = network.elapsed();
+        if elapsed < self.next_sample {
+            return Ok(());
+        }
+        while self.next_sample <= elapsed {
+            self.next_sample += self.interval;
+        }
+
+        let headings = headings(network);
+        if !self.header_written {
+            self.write_header(&headings)?;
+        }
+        let mut row = vec![format!("{:.3}", elapsed.as_secs_f64())];
+
+        let nodes = &network.nodes;
+        for &dir in &headings {
+            for lane in Lane::ALL {
+                row.push(nodes.iter().map(|node| node.cars_in[&(dir, lane)].len()).sum::<usize>().to_string());
+            }
+        }
+        for dir in &headings {
+            row.push(nodes.iter().map(|node| node.spawn_queues[dir].waiting.len()).sum::<usize>().to_string());
+        }
+
+        let cars = || nodes.iter().flat_map(|node| node.cars_in.values().flatten());
+        let in_intersection = cars().filter(|car| car.in_intersection).count();
+        let braking = cars().filter(|car| car.brake).count();
+        let (reserved, occupied) = nodes
+            .iter()
+            .map(|node| node.crossing_manager.zone_counts(node.now))
+            .fold((0, 0), |(r, o), (node_r, node_o)| (r + node_r, o + node_o));
+
+        row.extend([in_intersection, reserved, occupied, network.crossed(), braking].map(|v| v.to_string()));
+        writeln!(self.out, "{}", row.join(","))
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
@@ -1,19 +1,73 @@
-use crate::intersection::{Intersection, Direction, Route};
-use crate::crossing_manager::{ZoneIndex, ZoneReservation};
+use crate::intersection::{Direction, Lane, Route};
+use crate::crossing_manager::{PathZone, ZoneIndex, ZoneReservation};
 use crate::consts::*;
 use crate::car::Car;
+use crate::camera::Camera;
+use crate::hud::Hud;
+use crate::network::Network;
 use std::time::{SystemTime, Duration};
-use sdl2::render::{Texture, Canvas};
-use sdl2::video::Window;
-use sdl2::rect::Rect;
+use sdl2::render::{Texture, TextureCreator, Canvas, RenderTarget};
+use sdl2::rect::{Point, Rect};
 use sdl2::pixels::Color;
-use rand::prelude::*;
-use rand::rng;
 
-pub fn present_main_canvas(canvas: &mut Canvas<Window>, bg_texture: &Texture, intersection: &Intersection) {
+pub fn present_main_canvas<T: RenderTarget>(
+    canvas: &mut Canvas<T>,
+    texture_creator: &TextureCreator<T::Context>,
+    bg_texture: &Texture,
+    network: &Network,
+    camera: &Camera,
+    hud: &Hud,
+) {
+    canvas.set_draw_color(BACKGROUND_COLOR);
     canvas.clear();
-    canvas.copy(&bg_texture, None, None).unwrap();
-    intersection.draw(canvas);
+
+    // Each intersection draws in its own coordinates inside a viewport over its tile
+    let (view_x, view_y) = (camera.x.round() as i32, camera.y.round() as i32);
+    let view = Rect::new(
+        view_x,
+        view_y,
+        (SIMULATION_WINDOW_WIDTH as f32 / camera.zoom).ceil() as u32,
+        (SIMULATION_WINDOW_HEIGHT as f32 / camera.zoom).ceil() as u32,
+    );
+    canvas.set_scale(camera.zoom, camera.zoom).unwrap();
+    for (node, intersection) in network.nodes.iter().enumerate() {
+        let tile = network.tile(node);
+        let (x, y) = (tile.x(), tile.y());
+        if !tile.has_intersection(view) {
+            continue;
+        }
+
+        canvas.set_viewport(Rect::new(x - view_x, y - view_y, tile.width(), tile.height()));
+        if intersection.layout.has_diagonal_arms() {
+            // The background picture only has arms along the screen axes
+            canvas.set_draw_color(BACKGROUND_COLOR);
+            canvas.fill_rect(Rect::new(0, 0, tile.width(), tile.height())).unwrap();
+            canvas.set_draw_color(ROAD_COLOR);
+            for spans in intersection.layout.road_spans() {
+                canvas.draw_lines(&spans[..]).unwrap();
+            }
+        } else {
+            canvas.copy(bg_texture, None, None).unwrap();
+            canvas.set_draw_color(BACKGROUND_COLOR);
+            canvas.fill_rects(&intersection.layout.closed_arms()).unwrap();
+        }
+        intersection.draw(canvas);
+        if node == network.focus {
+            if network.nodes.len() > 1 {
+                canvas.set_draw_color(HUD_SELECTION_COLOR);
+                canvas.draw_rect(Rect::new(0, 0, tile.width(), tile.height())).unwrap();
+            }
+            if let Err(e) = hud.draw_selection(canvas, texture_creator, intersection) {
+                println!("Failed to draw selection: {}", e);
+            }
+        }
+    }
+    canvas.set_viewport(None);
+    canvas.set_scale(1.0, 1.0).unwrap();
+
+    if let Err(e) = hud.draw(canvas, texture_creator, network) {
+        println!("Failed to draw HUD: {}", e);
+    }
     canvas.present();
 }
 
@@ -21,208 +75,370 @@ pub fn round_two(n: f32) -> f32 {
     (n * 100.0).round() / 100.0
 }
 
-pub fn get_rnd_routes() -> Vec<Route> {
-    let mut routes = vec![Route::Left, Route::Right, Route::Straight];
-    let mut rng = rng();
-    routes.shuffle(&mut rng);
-    routes
+/// Route taken by a car heading `origin` that should leave heading `destination`,
+/// from which side of its heading the new one lies. U-turns are not supported.
+pub fn route_between(origin: Direction, destination: Direction) -> Option<Route> {
+    let eighths = origin.eighths_to(destination);
+    Route::ALL.into_iter().find(|route| route.eighths().rem_euclid(8) == eighths)
+}
+
+/// Heading a car leaves with after taking `route` from `origin`.
+pub fn destination_of(origin: Direction, route: Route) -> Direction {
+    origin.turned(route.eighths())
+}
+
+pub fn lane_routes(lane: Lane) -> &'static [Route] {
+    match lane {
+        Lane::Curb => LANE_ROUTES_CURB,
+        Lane::Middle => LANE_ROUTES_MIDDLE,
+        Lane::Median => LANE_ROUTES_MEDIAN,
+    }
+}
+
+/// Closest lane to `from` that can be used for `route`.
+pub fn target_lane(route: Route, from: Lane) -> Lane {
+    Lane::ALL
+        .into_iter()
+        .filter(|&lane| lane_routes(lane).contains(&route.side()))
+        .min_by_key(|&lane| lane.index().abs_diff(from.index()))
+        .unwrap_or(from)
+}
+
+/// Speeds (px/s) a car on this route may be offered for crossing, fastest first.
+pub fn crossing_speeds(route: Route) -> &'static [f64] {
+    match route.side() {
+        Route::Left => CROSSING_SPEEDS_LEFT,
+        Route::Right => CROSSING_SPEEDS_RIGHT,
+        _ => CROSSING_SPEEDS_STRAIGHT,
+    }
+}
+
+/// Extent of `rect` along a heading, in travel order.
+pub fn along(rect: Rect, heading: Direction) -> (i32, i32) {
+    rounded(project(&corners(rect), heading.unit()))
+}
+
+/// Unit vector pointing to the right of travel with `heading`.
+fn right_of(heading: Direction) -> (f64, f64) {
+    let (dx, dy) = heading.unit();
+    (-dy, dx)
 }
 
-pub fn get_rnd_direction() -> Direction {
-    let directions = [
-        Direction::North,
-        Direction::South,
-        Direction::East,
-        Direction::West,
-    ];
-    let mut rng = rng();
-    *directions.choose(&mut rng).unwrap()
+fn corners(rect: Rect) -> [(f64, f64); 4] {
+    let (left, right) = (rect.left() as f64, rect.right() as f64);
+    let (top, bottom) = (rect.top() as f64, rect.bottom() as f64);
+    [(left, top), (right, top), (right, bottom), (left, bottom)]
 }
 
-pub fn route_to_zone_path(dir: Direction, route: Route) -> Vec<ZoneIndex> {
-    match (dir, route) {
-        (Direction::South, Route::Left) => ZONES_FOR_SOUTH_LEFT.to_vec(),
-        (Direction::South, Route::Straight) => ZONES_FOR_SOUTH_STRAIGHT.to_vec(),
+fn project(points: &[(f64, f64)], (dx, dy): (f64, f64)) -> (f64, f64) {
+    points.iter().map(|&(x, y)| x * dx + y * dy).fold((f64::MAX, f64::MIN), |(low, high), p| (low.min(p), high.max(p)))
+}
+
+fn rounded((low, high): (f64, f64)) -> (i32, i32) {
+    (low.round() as i32, high.round() as i32)
+}
+
+/// Width and height on screen of a body `width` wide and `length` long travelling with `heading`.
+pub fn footprint(heading: Direction, width: u32, length: u32) -> (u32, u32) {
+    if heading.is_diagonal() {
+        let side = ((width + length) as f64 / std::f64::consts::SQRT_2).ceil() as u32;
+        (side, side)
+    } else if heading.is_vertical() {
+        (width, length)
+    } else {
+        (length, width)
+    }
+}
+
+/// A vehicle `width` wide and `length` long travelling with `heading`, centred
+/// in the box `bb` on screen. Off the screen axes the box is a good deal larger
+/// than the vehicle, so contact, gaps and lanes go by the body itself.
+#[derive(Debug, Clone, Copy)]
+pub struct Body {
+    pub bb: Rect,
+    pub heading: Direction,
+    pub width: u32,
+    pub length: u32,
+}
+
+impl Body {
+    /// Body with the top-left corner of its box at `(x, y)`.
+    pub fn new(x: i32, y: i32, heading: Direction, width: u32, length: u32) -> Self {
+        let (w, h) = footprint(heading, width, length);
+        Body { bb: Rect::new(x, y, w, h), heading, width, length }
+    }
+
+    /// Body centred as near `center` as whole pixels allow.
+    pub fn centred((cx, cy): (f64, f64), heading: Direction, width: u32, length: u32) -> Self {
+        let (w, h) = footprint(heading, width, length);
+        let x = (cx - w as f64 / 2.0).round() as i32;
+        let y = (cy - h as f64 / 2.0).round() as i32;
+        Body::new(x, y, heading, width, length)
+    }
+
+    pub fn center(&self) -> (f64, f64) {
+        (
+            self.bb.x() as f64 + self.bb.width() as f64 / 2.0,
+            self.bb.y() as f64 + self.bb.height() as f64 / 2.0,
+        )
+    }
+
+    /// Corners, clockwise on screen from the front left.
+    pub fn corners(&self) -> [(f64, f64); 4] {
+        let (cx, cy) = self.center();
+        let (ux, uy) = self.heading.unit();
+        let (rx, ry) = right_of(self.heading);
+        let (half_length, half_width) = (self.length as f64 / 2.0, self.width as f64 / 2.0);
+        [(1.0, -1.0), (1.0, 1.0), (-1.0, 1.0), (-1.0, -1.0)].map(|(a, b)| {
+            (
+                cx + a * half_length * ux + b * half_width * rx,
+                cy + a * half_length * uy + b * half_width * ry,
+            )
+        })
+    }
+
+    /// Extent along a heading, in travel order, before rounding.
+    pub fn extent_along(&self, heading: Direction) -> (f64, f64) {
+        project(&self.corners(), heading.unit())
+    }
+
+    /// Extent along a heading, in travel order.
+    pub fn along(&self, heading: Direction) -> (i32, i32) {
+        rounded(self.extent_along(heading))
+    }
+
+    /// Extent across a heading, from its left to its right.
+    pub fn across(&self, heading: Direction) -> (i32, i32) {
+        rounded(project(&self.corners(), right_of(heading)))
+    }
+
+    /// Same body moved `distance` px on along its heading, to the nearest whole pixel.
+    pub fn moved(&self, distance: f64) -> Body {
+        let (cx, cy) = self.center();
+        let (ux, uy) = self.heading.unit();
+        Body::centred((cx + distance * ux, cy + distance * uy), self.heading, self.width, self.length)
+    }
+
+    /// Same body moved sideways so its centre line runs through `point`.
+    pub fn shifted_across(&self, (px, py): (f64, f64)) -> Body {
+        let (cx, cy) = self.center();
+        let (rx, ry) = right_of(self.heading);
+        let offset = (px - cx) * rx + (py - cy) * ry;
+        Body::centred((cx + offset * rx, cy + offset * ry), self.heading, self.width, self.length)
+    }
+
+    /// Whether the two overlap with some area, as `Rect::has_intersection` has it for boxes.
+    pub fn overlaps(&self, other: &Body) -> bool {
+        let (mine, theirs) = (self.corners(), other.corners());
+        self.axes()
+            .into_iter()
+            .chain(other.axes())
+            .all(|axis| overlap(project(&mine, axis), project(&theirs, axis)))
+    }
+
+    pub fn overlaps_rect(&self, rect: Rect) -> bool {
+        let (mine, theirs) = (self.corners(), corners(rect));
+        self.axes()
+            .into_iter()
+            .chain([(1.0, 0.0), (0.0, 1.0)])
+            .all(|axis| overlap(project(&mine, axis), project(&theirs, axis)))
+    }
+
+    /// Distances the body can go on along its heading from here over which it
+    /// overlaps `rect`, ends excluded; `None` if it never does.
+    pub fn sweep(&self, rect: Rect) -> Option<(f64, f64)> {
+        let (mine, theirs) = (self.corners(), corners(rect));
+        let (ux, uy) = self.heading.unit();
+        let (mut from, mut to) = (f64::MIN, f64::MAX);
+        for axis in self.axes().into_iter().chain([(1.0, 0.0), (0.0, 1.0)]) {
+            let (low, high) = project(&mine, axis);
+            let (rect_low, rect_high) = project(&theirs, axis);
+            // How fast the body's shadow on this axis moves per pixel of travel
+            let rate = ux * axis.0 + uy * axis.1;
+            if rate.abs() < 1e-9 {
+                if !overlap((low, high), (rect_low, rect_high)) {
+                    return None;
+                }
+                continue;
+            }
+            let (a, b) = ((rect_low - high) / rate, (rect_high - low) / rate);
+            from = from.max(a.min(b));
+            to = to.min(a.max(b));
+        }
+        (from < to).then_some((from, to))
+    }
 
-        (Direction::North, Route::Left) => ZONES_FOR_NORTH_LEFT.to_vec(),
-        (Direction::North, Route::Straight) => ZONES_FOR_NORTH_STRAIGHT.to_vec(),
+    /// Outline for `Canvas::draw_lines`, closed.
+    pub fn outline(&self) -> [Point; 5] {
+        let corners = self.corners().map(|(x, y)| Point::new(x.round() as i32, y.round() as i32));
+        [corners[0], corners[1], corners[2], corners[3], corners[0]]
+    }
 
-        (Direction::East, Route::Left)  => ZONES_FOR_EAST_LEFT.to_vec(),
-        (Direction::East, Route::Straight) => ZONES_FOR_EAST_STRAIGHT.to_vec(),
+    fn axes(&self) -> [(f64, f64); 2] {
+        [self.heading.unit(), right_of(self.heading)]
+    }
+}
 
-        (Direction::West, Route::Left) => ZONES_FOR_WEST_LEFT.to_vec(),
-        (Direction::West, Route::Straight) => ZONES_FOR_WEST_STRAIGHT.to_vec(),
+fn overlap((low, high): (f64, f64), (other_low, other_high): (f64, f64)) -> bool {
+    low < other_high && other_low < high
+}
 
-        (_, Route::Right) => vec![],
+/// Horizontal runs of pixels filling the convex polygon through `points`, for
+/// `Canvas::draw_lines`. Runs alternate direction so the joins stay inside.
+pub fn polygon_spans(points: &[(f64, f64)]) -> Vec<Point> {
+    let top = points.iter().map(|p| p.1).fold(f64::MAX, f64::min).ceil() as i32;
+    let bottom = points.iter().map(|p| p.1).fold(f64::MIN, f64::max).floor() as i32;
+    let mut spans = Vec::new();
+    for y in top..=bottom {
+        let row = y as f64;
+        let crossings: Vec<f64> = points
+            .iter()
+            .zip(points.iter().cycle().skip(1))
+            .filter(|(a, b)| (a.1 <= row && row <= b.1) || (b.1 <= row && row <= a.1))
+            .filter(|(a, b)| a.1 != b.1)
+            .map(|(a, b)| a.0 + (row - a.1) * (b.0 - a.0) / (b.1 - a.1))
+            .collect();
+        if crossings.is_empty() {
+            continue;
+        }
+        let left = crossings.iter().copied().fold(f64::MAX, f64::min).round() as i32;
+        let right = crossings.iter().copied().fold(f64::MIN, f64::max).round() as i32;
+        if (y - top) % 2 == 0 {
+            spans.extend([Point::new(left, y), Point::new(right, y)]);
+        } else {
+            spans.extend([Point::new(right, y), Point::new(left, y)]);
+        }
     }
+    spans
+}
+
+/// Time window during which a road user `body_length` long, entering at
+/// `entry_time` and crossing at `speed`, holds `zone` on its path.
+pub fn zone_window(entry_time: SystemTime, zone: &PathZone, speed: f64, body_length: f64) -> (SystemTime, SystemTime) {
+    let time_in = entry_time + Duration::from_secs_f64(zone.reach / speed);
+    let time_out = entry_time + Duration::from_secs_f64((zone.leave + body_length + SAFE_DISTANCE_PX) / speed);
+    (time_in, time_out)
 }
 
 pub fn generate_zone_reservations(
     car_id: &str,
-    path: &[ZoneIndex],
+    path: &[PathZone],
     entry_time: SystemTime,
+    speed: f64,
+    body_length: f64,
 ) -> Vec<(ZoneIndex, ZoneReservation)> {
-    let zone_time = Duration::from_secs_f64(ZONE_LENGTH_PX / SPEED_PX_PER_SEC);
-    let occupy_time = Duration::from_secs_f64(CAR_HEIGHT_PX as f64 / SPEED_PX_PER_SEC);
-    let safe_gap = Duration::from_secs_f64(SAFE_DISTANCE_PX / SPEED_PX_PER_SEC);
-
     let mut reservations = Vec::new();
 
-    for (i, &zone) in path.iter().enumerate() {
-        let time_in = entry_time + zone_time * i as u32;
-        let time_out = time_in + zone_time + occupy_time + safe_gap;
+    for zone in path {
+        let (time_in, time_out) = zone_window(entry_time, zone, speed, body_length);
 
         let reservation = ZoneReservation {
-            _car_id: car_id.to_string(),
+            car_id: car_id.to_string(),
             time_in,
             time_out,
         };
 
-        reservations.push((zone, reservation));
+        reservations.push((zone.zone, reservation));
     }
 
     reservations
 }
 
-pub fn get_spawn_position(direction: Direction, route: Route) -> (i32, i32, i32) {
-    match (direction, route) {
-        (Direction::South, Route::Left) => SPAWN_POSITION_SOUTH_LEFT,
-        (Direction::South, Route::Straight) => SPAWN_POSITION_SOUTH_STRAIGHT,
-        (Direction::South, Route::Right) => SPAWN_POSITION_SOUTH_RIGHT,
-
-        (Direction::North, Route::Left) => SPAWN_POSITION_NORTH_LEFT,
-        (Direction::North, Route::Straight) => SPAWN_POSITION_NORTH_STRAIGHT,
-        (Direction::North, Route::Right) => SPAWN_POSITION_NORTH_RIGHT,
-
-        (Direction::East, Route::Left) => SPAWN_POSITION_EAST_LEFT,
-        (Direction::East, Route::Straight) => SPAWN_POSITION_EAST_STRAIGHT,
-        (Direction::East, Route::Right) => SPAWN_POSITION_EAST_RIGHT,
-
-        (Direction::West, Route::Left) => SPAWN_POSITION_WEST_LEFT,
-        (Direction::West, Route::Straight) => SPAWN_POSITION_WEST_STRAIGHT,
-        (Direction::West, Route::Right) => SPAWN_POSITION_WEST_RIGHT,
-    }
-}
-
-pub fn car_spawn_check(lane: &Vec<Car>, direction: Direction, x: i32, y: i32, height: i32) -> bool {
+pub fn car_spawn_check(lane: &Vec<Car>, body: &Body) -> bool {
     if lane.len() >= 4 { return false; }
 
     match lane.last() {
         Some(last_car) => {
-            let safe_distance = height;
-            let last_bb = last_car.bounding_box();
-            is_safe_distance_from_car(direction, x, y, height, &last_bb, safe_distance)
+            let safe_distance = body.length as i32;
+            is_safe_distance_from_car(body, &last_car.body(), safe_distance)
         }
         None => true,
     }
 }
 
+/// Clockwise rotation in degrees of a texture drawn facing south.
 pub fn get_angle_by_direction(direction: Direction) -> f64 {
-    match direction {
-        Direction::South => 0.0,
-        Direction::North => 180.0,
-        Direction::East => 270.0,
-        Direction::West => 90.0,
-    }
+    let (dx, dy) = direction.vector();
+    (-dx as f64).atan2(dy as f64).to_degrees().rem_euclid(360.0)
 }
 
+/// Where to draw a texture `width` by `height` from the top-left corner of the
+/// box, so that turned with `direction` it sits centred in it.
 pub fn get_direction_offset_by_direction(direction: Direction, width: u32, height: u32) -> (i32, i32) {
-    match direction {
-        Direction::North | Direction::South => (0, 0),
-        Direction::East | Direction::West => {
-            ((height as i32 - width as i32) / 2, -(height as i32 - width as i32) / 2)
-        }
-    }
+    let (box_width, box_height) = footprint(direction, width, height);
+    ((box_width as i32 - width as i32) / 2, (box_height as i32 - height as i32) / 2)
 }
 
-pub fn get_distance_by_direction(x: i32, y: i32, height: u32, direction: Direction) -> i32 {
-    match direction {
-        Direction::North => SIMULATION_WINDOW_HEIGHT as i32 - y,
-        Direction::South => y + height as i32,
-        Direction::East => x + height as i32,
-        Direction::West => SIMULATION_WINDOW_WIDTH as i32 - x,
-    }
+pub fn is_safe_distance_from_car(body: &Body, other: &Body, safe_distance: i32) -> bool {
+    let (_, front) = body.along(body.heading);
+    front + safe_distance <= other.along(body.heading).0
 }
 
-pub fn is_past_entry_by_direction(x: i32, y: i32, height: u32, direction: Direction) -> bool {
-    match direction {
-        Direction::North => y <= 550,
-        Direction::South => y + height as i32 >= 350,
-        Direction::East => x + height as i32 >= 350,
-        Direction::West => x <= 550,
-    }
+pub fn gap_by_direction(
+    body: &Body,
+    other: &Body,
+    direction: Direction,
+) -> Option<i32> {
+    let gap = other.along(direction).0 - body.along(direction).1;
+    let (self_left, self_right) = body.across(direction);
+    let (other_left, other_right) = other.across(direction);
+    let same_lane = self_left < other_right && self_right > other_left;
+
+    if same_lane { Some(gap.max(0)) } else { None }
 }
 
-pub fn is_safe_distance_from_car(
-    direction: Direction,
-    x: i32,
-    y: i32,
-    height: i32,
-    other_bb: &Rect,
-    safe_distance: i32,
+/// Gap acceptance for moving into a lane at `merged`: the gap ahead must cover the
+/// car's safe headway, and the new follower must not be forced into hard braking.
+pub fn accepts_gap(
+    merged: &Body,
+    speed: f64,
+    leader: Option<&Car>,
+    follower: Option<&Car>,
 ) -> bool {
-    match direction {
-        Direction::North => y >= other_bb.y() + other_bb.height() as i32 + safe_distance,
-        Direction::South => y + height + safe_distance <= other_bb.y(),
-        Direction::East => x + height + safe_distance <= other_bb.x(),
-        Direction::West => x >= other_bb.x() + other_bb.width() as i32 + safe_distance,
-    }
+    let lead_ok = leader.is_none_or(|car| {
+        let body = car.body();
+        !merged.overlaps(&body)
+            && gap_by_direction(merged, &body, merged.heading)
+                .is_none_or(|gap| gap as f64 >= BRAKE_DISTANCE_PX as f64 + speed * IDM_TIME_HEADWAY)
+    });
+
+    let lag_ok = follower.is_none_or(|car| {
+        let body = car.body();
+        !merged.overlaps(&body)
+            && gap_by_direction(&body, merged, car.direction).is_none_or(|gap| {
+                let accel = idm_acceleration(car.speed, SPEED_PX_PER_SEC, car.time_headway(), Some((gap as f64, car.speed - speed)));
+                accel >= -IDM_COMFORT_DECELERATION
+            })
+    });
+
+    lead_ok && lag_ok
 }
 
-pub fn proximity_check_by_direction(
-    self_bb: &Rect,
-    other_bb: &Rect,
-    direction: Direction,
-    safe_distance: i32,
-) -> bool {
-    match direction {
-        Direction::North => {
-            let this_front = self_bb.top();
-            let other_rear = other_bb.bottom();
-            this_front <= other_rear + safe_distance
-                && self_bb.x() < other_bb.right()
-                && self_bb.right() > other_bb.x()
-        }
-        Direction::South => {
-            let this_front = self_bb.bottom();
-            let other_rear = other_bb.top();
-            this_front >= other_rear - safe_distance
-                && self_bb.x() < other_bb.right()
-                && self_bb.right() > other_bb.x()
+/// Intelligent Driver Model acceleration (px/s^2) keeping `time_headway` seconds
+/// behind the car ahead. `gap` and `approach_rate` describe it, `None` means free road.
+pub fn idm_acceleration(speed: f64, desired_speed: f64, time_headway: f64, leader: Option<(f64, f64)>) -> f64 {
+    let free_road = 1.0 - (speed / desired_speed).powi(IDM_ACCELERATION_EXPONENT);
+
+    let interaction = match leader {
+        Some((gap, approach_rate)) => {
+            let desired_gap = BRAKE_DISTANCE_PX as f64
+                + (speed * time_headway
+                    + speed * approach_rate
+                        / (2.0 * (IDM_MAX_ACCELERATION * IDM_COMFORT_DECELERATION).sqrt()))
+                .max(0.0);
+            (desired_gap / gap.max(1.0)).powi(2)
         }
-        Direction::East => {
-            let this_front = self_bb.right();
-            let other_rear = other_bb.x();
-            this_front >= other_rear - safe_distance
-                && self_bb.y() < other_bb.bottom()
-                && self_bb.bottom() > other_bb.y()
-        }
-        Direction::West => {
-            let this_front = self_bb.x();
-            let other_rear = other_bb.right();
-            this_front <= other_rear + safe_distance
-                && self_bb.y() < other_bb.bottom()
-                && self_bb.bottom() > other_bb.y()
-        }
-    }
-}
+        None => 0.0,
+    };
 
-pub fn apply_movement_for_direction(x: &mut i32, y: &mut i32, distance: i32, direction: Direction) {
-    match direction {
-        Direction::North => *y -= distance,
-        Direction::South => *y += distance,
-        Direction::East => *x += distance,
-        Direction::West => *x -= distance,
-    }
+    IDM_MAX_ACCELERATION * (free_road - interaction)
 }
 
-pub fn has_exited_by_direction(x: i32, y: i32, height: u32, direction: Direction) -> bool {
-    match direction {
-        Direction::North => y + height as i32 <= 0,
-        Direction::South => y >= SIMULATION_WINDOW_HEIGHT as i32,
-        Direction::West => x + height as i32 <= 0,
-        Direction::East => x >= SIMULATION_WINDOW_WIDTH as i32 + height as i32,
-    }
+/// Moves `(x, y)` on by `steps` steps of `direction.vector()`.
+pub fn apply_movement_for_direction(x: &mut i32, y: &mut i32, steps: i32, direction: Direction) {
+    let (dx, dy) = direction.vector();
+    *x += dx * steps;
+    *y += dy * steps;
 }
 
 pub fn calculate_speed_statistics(cars: &[Car]) -> (f32, f32, f32) {
@@ -293,6 +509,24 @@ pub fn calculate_duration_statistics(cars: &[Car]) -> (f32, f32, f32) {
     }
 }
 
+/// Min/max/avg time cars spent in the upstream queue before entering the road.
+pub fn calculate_wait_statistics(cars: &[Car]) -> (f32, f32, f32) {
+    let waits: Vec<f32> = cars
+        .iter()
+        .filter_map(|car| car.time_enter.duration_since(car.time_requested).ok())
+        .map(|wait| wait.as_secs_f32())
+        .collect();
+
+    if waits.is_empty() {
+        return (0.0, 0.0, 0.0);
+    }
+
+    let min_wait = waits.iter().copied().fold(f32::MAX, f32::min);
+    let max_wait = waits.iter().copied().fold(0.0, f32::max);
+    let avg_wait = waits.iter().sum::<f32>() / waits.len() as f32;
+    (round_two(min_wait), round_two(max_wait), round_two(avg_wait))
+}
+
 pub fn calculate_time_difference(scheduled: SystemTime, actual: SystemTime) -> f64 {
     scheduled
         .duration_since(actual)
@@ -300,14 +534,6 @@ pub fn calculate_time_difference(scheduled: SystemTime, actual: SystemTime) -> f
         .unwrap_or_else(|e| e.duration().as_secs_f64())
 }
 
-pub fn calculate_speed_from_distance_time(distance: i32, time_left: f64, default_speed: f64) -> i32 {
-    if time_left > 0.0 {
-        (distance as f64 / time_left).round() as i32
-    } else {
-        default_speed as i32
-    }
-}
-
 pub fn get_color_by_state(collided: bool, brake: bool, in_intersection: bool) -> Color {
     if collided {
         Color::MAGENTA // Magenta for collision
@@ -327,27 +553,4 @@ pub fn create_origin_rect(x: i32, y: i32, size: i32) -> Rect {
         size as u32,
         size as u32,
     )
-}
-
-pub fn get_position_after_turn(
-    x: i32,
-    y: i32,
-    distance: i32,
-    direction: Direction,
-    turn_type: Route,
-) -> (i32, i32, Direction) {
-    match (direction, turn_type) {
-        (Direction::North, Route::Right) => (x + distance, 558, Direction::East),
-        (Direction::South, Route::Right) => (x - (CAR_HEIGHT_PX/2) as i32 - distance, 308, Direction::West),
-        (Direction::East, Route::Right) => (308, y + distance, Direction::South),
-        (Direction::West, Route::Right) => (558, y - (CAR_HEIGHT_PX/2) as i32 - distance, Direction::North),
-        
-        (Direction::North, Route::Left) => (x - (CAR_HEIGHT_PX/2) as i32 - distance, 408, Direction::West),
-        (Direction::South, Route::Left) => (x + distance, 458, Direction::East),
-        (Direction::East, Route::Left) => (458, y - (CAR_HEIGHT_PX/2) as i32 - distance, Direction::North),
-        (Direction::West, Route::Left) => (408, y + distance, Direction::South),
-        
-        // Invalid combinations (should not happen)
-        _ => (x, y, direction),
-    }
 }
\ No newline at end of file
@@ -0,0 +1,384 @@
+use crate::car::VehicleClass;
+use crate::crossing_manager::{Approach, ZoneIndex};
+use crate::intersection::{Direction, Lane, Route};
+use crate::speed_profile::plan_arrival;
+use rand::prelude::*;
+use rand::rng;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, SystemTime};
+
+use crate::consts::*;
+
+/// Window a zone is held for a car, as confirmed to it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ZoneSlot {
+    pub zone: ZoneIndex,
+    pub time_in: SystemTime,
+    pub time_out: SystemTime,
+}
+
+/// Vehicle-to-infrastructure messages between an autonomous car and the crossing
+/// manager. Each exchange is about one of the car's requests, numbered by `seq`:
+/// a newer request replaces the last one, and older numbers are stale. Request 0
+/// is made on the car's behalf by the intersection it crosses before this one.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Message {
+    /// Car asks for a slot, from where it is at time `at`.
+    Request {
+        car_id: String,
+        seq: u32,
+        at: SystemTime,
+        dir: Direction,
+        lane: Lane,
+        route: Route,
+        distance_to_entry: f64,
+        speed: f64,
+        /// Soonest the car could be at the entry line.
+        arrival: SystemTime,
+        length: f64,
+        width: f64,
+        class: VehicleClass,
+        /// When it first asked to enter, which its claim on a slot grows from.
+        requested_at: SystemTime,
+    },
+    /// Manager booked `entry_time` at `crossing_speed` for request `seq`. A
+    /// `committed` slot is the car's for good; any other may still be moved.
+    /// `displaced` when the car's last slot went to one with a stronger claim.
+    Confirm {
+        car_id: String,
+        seq: u32,
+        entry_time: SystemTime,
+        crossing_speed: f64,
+        slots: Vec<ZoneSlot>,
+        committed: bool,
+        displaced: bool,
+    },
+    /// Nothing booked for request `seq`; the car waits short of the entry line.
+    Reject { car_id: String, seq: u32, displaced: bool },
+    /// Car gives up what it holds for request `seq` and everything before it.
+    Cancel { car_id: String, seq: u32 },
+    /// Car nearing the entry line asks to go ahead on the slot of request `seq`.
+    Update {
+        car_id: String,
+        seq: u32,
+        at: SystemTime,
+        distance_to_entry: f64,
+        speed: f64,
+    },
+}
+
+impl Message {
+    pub fn car_id(&self) -> &str {
+        match self {
+            Message::Request { car_id, .. }
+            | Message::Confirm { car_id, .. }
+            | Message::Reject { car_id, .. }
+            | Message::Cancel { car_id, .. }
+            | Message::Update { car_id, .. } => car_id,
+        }
+    }
+}
+
+/// One-way radio link: every message arrives `latency` after it was sent, in
+/// order, unless it is lost. Messages travel as JSON, as they would between
+/// the cars and a manager running as a separate process.
+pub struct Channel {
+    pub latency: Duration,
+    /// Chance of any one message never arriving.
+    pub loss: f64,
+    pub sent: usize,
+    pub lost: usize,
+    in_flight: VecDeque<(SystemTime, String)>,
+}
+
+impl Channel {
+    pub fn new() -> Self {
+        Channel { latency: Duration::ZERO, loss: 0.0, sent: 0, lost: 0, in_flight: VecDeque::new() }
+    }
+
+    pub fn send(&mut self, now: SystemTime, message: &Message) {
+        self.sent += 1;
+        if self.loss > 0.0 && rng().random_bool(self.loss) {
+            self.lost += 1;
+            return;
+        }
+        let text = serde_json::to_string(message).expect("V2I message should serialize");
+        self.in_flight.push_back((now + self.latency, text));
+    }
+
+    /// Messages that have arrived by `now`, oldest first.
+    pub fn receive(&mut self, now: SystemTime) -> Vec<Message> {
+        let mut arrived = Vec::new();
+        while self.in_flight.front().is_some_and(|&(at, _)| at <= now) {
+            let (_, text) = self.in_flight.pop_front().unwrap();
+            arrived.push(serde_json::from_str(&text).expect("V2I message should parse"));
+        }
+        arrived
+    }
+}
+
+/// Where a car stands with the crossing manager.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ClientState {
+    /// No request open; the car waits short of the entry line.
+    Idle,
+    /// Request `seq` sent at `since` and not answered yet.
+    Requested { seq: u32, since: SystemTime },
+    /// Slot booked for request `seq`, which the manager may still move.
+    Confirmed { seq: u32 },
+    /// Asked at `since` to go ahead on the slot of request `seq`.
+    Committing { seq: u32, since: SystemTime },
+    /// The slot of request `seq` is the car's for good.
+    Cleared { seq: u32 },
+    /// Everything given up, e.g. once through the intersection.
+    Closed,
+}
+
+/// What a car learns from an answer of the manager; `displaced` as in the message.
+pub enum Answer {
+    Slot { entry_time: SystemTime, crossing_speed: f64, displaced: bool },
+    Refused { displaced: bool },
+}
+
+/// Car side of the protocol. Answers to anything but the latest request are
+/// ignored, and one overdue by `V2I_REPLY_TIMEOUT` is asked for again.
+pub struct Client {
+    pub state: ClientState,
+    last_seq: u32,
+}
+
+impl Client {
+    pub fn new() -> Self {
+        Client { state: ClientState::Idle, last_seq: 0 }
+    }
+
+    /// Client for a car whose slot was booked before it was on the road, as request 0.
+    pub fn handed_over() -> Self {
+        Client { state: ClientState::Confirmed { seq: 0 }, last_seq: 0 }
+    }
+
+    /// Latest request, unless the car has given up.
+    pub fn seq(&self) -> Option<u32> {
+        match self.state {
+            ClientState::Idle | ClientState::Closed => None,
+            ClientState::Requested { seq, .. }
+            | ClientState::Confirmed { seq }
+            | ClientState::Committing { seq, .. }
+            | ClientState::Cleared { seq } => Some(seq),
+        }
+    }
+
+    pub fn is_cleared(&self) -> bool {
+        matches!(self.state, ClientState::Cleared { .. })
+    }
+
+    pub fn timed_out(&self, now: SystemTime) -> bool {
+        match self.state {
+            ClientState::Requested { since, .. } | ClientState::Committing { since, .. } => since + V2I_REPLY_TIMEOUT <= now,
+            _ => false,
+        }
+    }
+
+    /// Open a new request, superseding the last one; returns its number.
+    pub fn open(&mut self, now: SystemTime) -> u32 {
+        self.last_seq += 1;
+        self.state = ClientState::Requested { seq: self.last_seq, since: now };
+        self.last_seq
+    }
+
+    /// Ask to go ahead on the confirmed slot, or ask again; returns the request it is for.
+    pub fn commit(&mut self, now: SystemTime) -> Option<u32> {
+        match self.state {
+            ClientState::Confirmed { seq } | ClientState::Committing { seq, .. } => {
+                self.state = ClientState::Committing { seq, since: now };
+                Some(seq)
+            }
+            _ => None,
+        }
+    }
+
+    /// Give everything up; returns the latest request, to cancel.
+    pub fn close(&mut self) -> u32 {
+        self.state = ClientState::Closed;
+        self.last_seq
+    }
+
+    pub fn receive(&mut self, message: &Message) -> Option<Answer> {
+        let current = self.seq()?;
+        match *message {
+            Message::Confirm { seq, entry_time, crossing_speed, committed, displaced, .. } if seq == current => {
+                if committed {
+                    self.state = ClientState::Cleared { seq };
+                } else if self.is_cleared() {
+                    // An earlier answer overtaken by the go-ahead
+                    return None;
+                } else {
+                    self.state = ClientState::Confirmed { seq };
+                }
+                Some(Answer::Slot { entry_time, crossing_speed, displaced })
+            }
+            Message::Reject { seq, displaced, .. } if seq == current => {
+                self.state = ClientState::Idle;
+                Some(Answer::Refused { displaced })
+            }
+            _ => None,
+        }
+    }
+
+    /// Short description for the inspector.
+    pub fn status(&self) -> String {
+        match self.state {
+            ClientState::Idle => "idle".to_string(),
+            ClientState::Requested { seq, .. } => format!("requested #{}", seq),
+            ClientState::Confirmed { seq } => format!("confirmed #{}", seq),
+            ClientState::Committing { seq, .. } => format!("asking to go #{}", seq),
+            ClientState::Cleared { seq } => format!("cleared #{}", seq),
+            ClientState::Closed => "closed".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionState {
+    /// Slot booked; the manager may still move or withdraw it.
+    Booked,
+    /// The car was told to go ahead; its slot is no longer touched.
+    Committed,
+    Rejected,
+}
+
+/// Manager side of the protocol, one per car: the latest request heard from it,
+/// what the car last said about where it is, and what it was told.
+pub struct Session {
+    pub seq: u32,
+    pub state: SessionState,
+    pub heard_at: SystemTime,
+    pub slot: Option<(SystemTime, f64)>,
+    /// Where the car said it was at time `at`.
+    pub approach: Approach,
+    pub at: SystemTime,
+    pub class: VehicleClass,
+    pub requested_at: SystemTime,
+}
+
+impl Session {
+    /// The car's claim on a slot right now, see `VehicleClass::priority`.
+    pub fn priority(&self, now: SystemTime) -> f64 {
+        self.class.priority(now.duration_since(self.requested_at).unwrap_or_default())
+    }
+
+    /// Where the car should be by `now`, and from when that holds: as it last
+    /// said, moved along the plan to its slot. A car not on the road yet is
+    /// where it said it would be, once it is there.
+    pub fn whereabouts(&self, now: SystemTime) -> (Approach, SystemTime) {
+        let Ok(elapsed) = now.duration_since(self.at) else {
+            return (self.approach, self.at);
+        };
+        let Approach { distance_to_entry, speed, .. } = self.approach;
+        let planned = self.slot.and_then(|(entry_time, crossing_speed)| {
+            let time_left = entry_time.duration_since(self.at).unwrap_or_default().as_secs_f64();
+            plan_arrival(speed, distance_to_entry, time_left, crossing_speed)
+        });
+        let approach = match planned {
+            Some(profile) => {
+                let t = elapsed.as_secs_f64();
+                let distance_to_entry = (distance_to_entry - profile.distance_at(t)).max(0.0);
+                Approach { distance_to_entry, speed: profile.speed_at(t), ..self.approach }
+            }
+            None => self.approach,
+        };
+        (approach, now)
+    }
+
+    /// Whether the car can still stop short of the entry line, or has.
+    pub fn can_stop(&self, now: SystemTime) -> bool {
+        let (approach, _) = self.whereabouts(now);
+        approach.distance_to_entry >= AV_HOLD_DISTANCE_PX || approach.speed < STOPPED_SPEED_PX_PER_SEC
+    }
+}
+
+/// The crossing manager's end of the radio links, with a session per car.
+pub struct Server {
+    pub sessions: HashMap<String, Session>,
+    /// Cars to the manager.
+    pub uplink: Channel,
+    /// Manager to the cars.
+    pub downlink: Channel,
+    /// Answers a car gave up waiting for.
+    pub timeouts: usize,
+}
+
+impl Server {
+    pub fn new() -> Self {
+        Server { sessions: HashMap::new(), uplink: Channel::new(), downlink: Channel::new(), timeouts: 0 }
+    }
+
+    /// Whether request `seq` is newer than anything heard from the car so far.
+    /// A car back for another crossing, having asked to enter again since, starts afresh.
+    pub fn is_new(&self, car_id: &str, seq: u32, requested_at: SystemTime) -> bool {
+        self.sessions
+            .get(car_id)
+            .is_none_or(|session| seq > session.seq || requested_at != session.requested_at)
+    }
+
+    /// Session for a request, with nothing booked yet.
+    pub fn open(&mut self, now: SystemTime, request: &Message) {
+        let &Message::Request {
+            ref car_id, seq, at, dir, lane, route, distance_to_entry, speed, length, class, requested_at, ..
+        } = request
+        else {
+            return;
+        };
+        let approach = Approach { dir, lane, route, distance_to_entry, speed, length };
+        let session = Session { seq, state: SessionState::Rejected, heard_at: now, slot: None, approach, at, class, requested_at };
+        self.sessions.insert(car_id.clone(), session);
+    }
+
+    /// End the session of a car that gave up request `seq`; false if it was stale.
+    pub fn close(&mut self, car_id: &str, seq: u32) -> bool {
+        if self.sessions.get(car_id).is_none_or(|session| seq < session.seq) {
+            return false;
+        }
+        self.sessions.remove(car_id);
+        true
+    }
+
+    /// Tell a car what it holds for its latest request: an entry time and
+    /// crossing speed, confirmed with the zone windows in `slots`, or nothing.
+    pub fn answer(
+        &mut self, now: SystemTime, car_id: &str, slot: Option<(SystemTime, f64)>, slots: Vec<ZoneSlot>, committed: bool,
+        displaced: bool,
+    ) {
+        let Some(session) = self.sessions.get_mut(car_id) else {
+            return;
+        };
+        let seq = session.seq;
+        session.slot = slot;
+        let message = match slot {
+            Some((entry_time, crossing_speed)) => {
+                session.state = if committed { SessionState::Committed } else { SessionState::Booked };
+                Message::Confirm {
+                    car_id: car_id.to_string(),
+                    seq,
+                    entry_time,
+                    crossing_speed,
+                    slots,
+                    committed,
+                    displaced,
+                }
+            }
+            None => {
+                session.state = SessionState::Rejected;
+                Message::Reject { car_id: car_id.to_string(), seq, displaced }
+            }
+        };
+        self.downlink.send(now, &message);
+    }
+
+    /// Forget sessions not heard from for `V2I_SESSION_TTL` that hold nothing
+    /// any more, for instance of cars whose cancel was lost.
+    pub fn expire(&mut self, now: SystemTime, holds_zones: impl Fn(&str) -> bool) {
+        self.sessions.retain(|car_id, session| session.heard_at + V2I_SESSION_TTL > now || holds_zones(car_id));
+    }
+}